@@ -34,6 +34,38 @@ pub fn criterion_benchmark(c: &mut Criterion) {
             );
         }
     }
+
+    // A feed of placeholders, all decoded at the same size.
+    let blurhashes: Vec<blurhash::Blurhash> =
+        cases.iter().map(|case| case.parse().unwrap()).collect();
+    for size in [32, 64] {
+        c.bench_with_input(
+            BenchmarkId::new("decode feed with fresh tables", size),
+            &size,
+            |b, &size| {
+                let mut buf = vec![0u8; size as usize * size as usize * 4];
+                b.iter(|| {
+                    for case in cases {
+                        blurhash::decode_into(&mut buf, black_box(case), size, size, 1.0).unwrap()
+                    }
+                })
+            },
+        );
+
+        c.bench_with_input(
+            BenchmarkId::new("decode feed through one Decoder", size),
+            &size,
+            |b, &size| {
+                let mut decoder = blurhash::Decoder::new(size, size);
+                let mut buf = vec![0u8; size as usize * size as usize * 4];
+                b.iter(|| {
+                    for case in &blurhashes {
+                        decoder.decode_into(&mut buf, black_box(case), 1.0).unwrap()
+                    }
+                })
+            },
+        );
+    }
 }
 
 criterion_group!(benches, criterion_benchmark);
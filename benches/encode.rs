@@ -1,4 +1,4 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
 use image::GenericImageView;
 
 pub fn lenna(c: &mut Criterion) {
@@ -11,6 +11,22 @@ pub fn lenna(c: &mut Criterion) {
         c.bench_function(&format!("encode {}", case), |b| {
             b.iter(|| blurhash::encode(4, 3, width, height, black_box(&img)).unwrap());
         });
+
+        for components in [(1, 1), (4, 3), (6, 6), (9, 9)] {
+            c.bench_with_input(
+                BenchmarkId::new(
+                    format!("encode components {}", case),
+                    format!("{:?}", components),
+                ),
+                &components,
+                |b, &(components_x, components_y)| {
+                    b.iter(|| {
+                        blurhash::encode(components_x, components_y, width, height, black_box(&img))
+                            .unwrap()
+                    })
+                },
+            );
+        }
     }
 }
 
@@ -38,6 +38,43 @@ fn generate_srgb_lookup() -> [f32; 256] {
     table
 }
 
+/// The number of fractional bits of linear values in the fixed-point decoder.
+const FIXED_LINEAR_BITS: u32 = 20;
+
+fn generate_srgb_fixed_lookup() -> [i32; 256] {
+    let mut table = [0i32; 256];
+    for (i, val) in table.iter_mut().enumerate() {
+        let v = i as f64 / 255.;
+        let linear = if v <= 0.04045 {
+            v / 12.92
+        } else {
+            f64::powf((v + 0.055) / 1.055, 2.4)
+        };
+        *val = (linear * (1 << FIXED_LINEAR_BITS) as f64).round() as i32;
+    }
+    table
+}
+
+/// For every sRGB value, the smallest fixed-point linear value that `linear_to_srgb` maps to it
+/// or to a larger one.
+fn generate_linear_to_srgb_thresholds() -> [i32; 256] {
+    let one = 1 << FIXED_LINEAR_BITS;
+    let mut table = [0i32; 256];
+    for (srgb, val) in table.iter_mut().enumerate() {
+        let (mut low, mut high) = (0, one);
+        while low < high {
+            let mid = (low + high) / 2;
+            if (linear_to_srgb(mid as f32 / one as f32) as usize) < srgb {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        *val = low;
+    }
+    table
+}
+
 fn write_srgb(f: &mut std::fs::File) {
     writeln!(
         f,
@@ -47,10 +84,19 @@ fn write_srgb(f: &mut std::fs::File) {
         const LINEAR_TO_SRGB_LOOKUP_SIZE: usize = {};
         #[cfg(feature = \"fast-linear-to-srgb\")]
         static LINEAR_TO_SRGB_LOOKUP: [u8; LINEAR_TO_SRGB_LOOKUP_SIZE] = {:?};
+        #[cfg(feature = \"fixed-point\")]
+        pub const FIXED_LINEAR_BITS: u32 = {};
+        #[cfg(feature = \"fixed-point\")]
+        static SRGB_FIXED_LOOKUP: [i32; 256] = {:?};
+        #[cfg(feature = \"fixed-point\")]
+        static LINEAR_TO_SRGB_THRESHOLDS: [i32; 256] = {:?};
         ",
         generate_srgb_lookup(),
         LINEAR_TO_SRGB_LOOKUP_SIZE,
-        generate_linear_to_srgb_lookup()
+        generate_linear_to_srgb_lookup(),
+        FIXED_LINEAR_BITS,
+        generate_srgb_fixed_lookup(),
+        generate_linear_to_srgb_thresholds()
     )
     .unwrap();
 }
@@ -74,6 +120,34 @@ fn write_base83(f: &mut std::fs::File) {
     writeln!(f, "const CHARACTERS_INV_INVALID: u8 = {};", max_plus_one).unwrap();
 }
 
+/// The number of segments the quarter-wave cosine table divides 0 to PI / 2 into.
+const COSINE_SEGMENTS: usize = 1024;
+/// The number of fractional bits of the cosines in the fixed-point decoder.
+const COSINE_BITS: u32 = 30;
+
+fn write_cosine(f: &mut std::fs::File) {
+    // One entry past PI / 2, so that interpolating at exactly PI / 2 stays in bounds.
+    let table: Vec<i32> = (0..COSINE_SEGMENTS + 2)
+        .map(|i| {
+            let angle = std::f64::consts::FRAC_PI_2 * i as f64 / COSINE_SEGMENTS as f64;
+            (angle.cos() * (1u32 << COSINE_BITS) as f64).round() as i32
+        })
+        .collect();
+    writeln!(
+        f,
+        "
+        const COSINE_SEGMENTS: u64 = {};
+        const COSINE_BITS: u32 = {};
+        static COSINE_LOOKUP: [i32; {}] = {:?};
+        ",
+        COSINE_SEGMENTS,
+        COSINE_BITS,
+        table.len(),
+        table
+    )
+    .unwrap();
+}
+
 fn main() {
     let out_dir = std::env::var("OUT_DIR").unwrap();
     let out_dir = std::path::PathBuf::from(out_dir);
@@ -83,4 +157,7 @@ fn main() {
 
     let mut f = std::fs::File::create(out_dir.join("base83_lookup.rs")).unwrap();
     write_base83(&mut f);
+
+    let mut f = std::fs::File::create(out_dir.join("cosine_lookup.rs")).unwrap();
+    write_cosine(&mut f);
 }
@@ -1,4 +1,5 @@
-# Blur the test images using the official blurhash-python package.
+# Blur the test images using the Python bindings in ../python, which can be installed with
+# `pip install ../python`.
 # The results are used as reference images in the tests of blurhash-rs.
 
 import blurhash
@@ -12,5 +13,5 @@ for path in images:
         hash = blurhash.encode(image, x_components=4, y_components=3)
         width, height = image.size
 
-    result = blurhash.decode(hash, width, height, mode=blurhash.PixelMode.RGBA)
+    result = Image.frombytes("RGBA", (width, height), blurhash.decode(hash, width, height))
     result.save(path.split(".")[0] + "_blurred.png")
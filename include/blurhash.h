@@ -0,0 +1,92 @@
+#ifndef BLURHASH_H
+#define BLURHASH_H
+
+/* This file is generated by cbindgen from src/ffi.rs. Do not edit it by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The length of the longest blurhash, of 9 by 9 components, without the terminating NUL.
+ */
+#define BLURHASH_MAX_LEN (4 + ((2 * 9) * 9))
+
+/**
+ * The outcome of a call, where every error corresponds to a variant of `blurhash::Error`.
+ */
+typedef enum {
+  BLURHASH_STATUS_OK = 0,
+  /**
+   * A required pointer argument was NULL.
+   */
+  BLURHASH_STATUS_NULL_POINTER = 1,
+  BLURHASH_STATUS_HASH_TOO_SHORT = 2,
+  BLURHASH_STATUS_LENGTH_MISMATCH = 3,
+  BLURHASH_STATUS_INVALID_ASCII = 4,
+  BLURHASH_STATUS_INVALID_BASE83 = 5,
+  BLURHASH_STATUS_COMPONENTS_OUT_OF_RANGE = 6,
+  BLURHASH_STATUS_VALUE_OUT_OF_RANGE = 7,
+  BLURHASH_STATUS_BUFFER_SIZE_MISMATCH = 8,
+  BLURHASH_STATUS_STRIDE_TOO_SMALL = 9,
+  BLURHASH_STATUS_INVALID_DIMENSIONS = 10,
+  BLURHASH_STATUS_INVALID_BUDGET = 11,
+} BlurhashStatus;
+
+/**
+ * Calculates the blurhash for a tightly packed RGBA8 image of `width * height * 4` bytes.
+ *
+ * The hash is written to `out` as a NUL-terminated string. `out_len` must leave room for
+ * `4 + 2 * components_x * components_y` characters and the NUL, which
+ * `BLURHASH_MAX_LEN + 1` always does.
+ *
+ * # Safety
+ *
+ * `rgba` must point to `rgba_len` readable bytes, and `out` to `out_len` writable bytes.
+ */
+BlurhashStatus blurhash_encode(uint32_t components_x,
+                               uint32_t components_y,
+                               uint32_t width,
+                               uint32_t height,
+                               const uint8_t *rgba,
+                               size_t rgba_len,
+                               char *out,
+                               size_t out_len);
+
+/**
+ * Decodes a NUL-terminated blurhash to a `width` by `height` RGBA8 image, into a buffer of
+ * exactly `width * height * 4` bytes.
+ *
+ * The punch parameter can be used to de- or increase the contrast of the
+ * resulting image. Decoding does not allocate.
+ *
+ * # Safety
+ *
+ * `blurhash` must be a NUL-terminated string, and `pixels` must point to `pixels_len` writable
+ * bytes.
+ */
+BlurhashStatus blurhash_decode_into(const char *blurhash,
+                                    uint32_t width,
+                                    uint32_t height,
+                                    float punch,
+                                    uint8_t *pixels,
+                                    size_t pixels_len);
+
+/**
+ * Checks that a NUL-terminated blurhash is well-formed, without decoding it.
+ *
+ * On success, the component counts are stored in `components_x` and `components_y`, unless they
+ * are NULL.
+ *
+ * # Safety
+ *
+ * `blurhash` must be a NUL-terminated string, and `components_x` and `components_y` must each be
+ * NULL or point to a writable `uint32_t`.
+ */
+BlurhashStatus blurhash_validate(const char *blurhash,
+                                 uint32_t *components_x,
+                                 uint32_t *components_y);
+
+#endif  /* BLURHASH_H */
@@ -0,0 +1,101 @@
+//! Python bindings for blurhash-rs.
+//!
+//! `encode` and `decode` release the GIL while they run, so that several threads can hash images
+//! in parallel.
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::pybacked::PyBackedBytes;
+use pyo3::types::{PyByteArray, PyBytes};
+
+fn to_py_err(error: blurhash_rs::Error) -> PyErr {
+    PyValueError::new_err(error.to_string())
+}
+
+/// Calculates the blurhash of an image.
+///
+/// The image is either a `PIL.Image.Image`, which is converted to RGBA, or a `bytes` or `bytearray`
+/// of tightly packed RGBA8 pixels, in which case `width` and `height` are required. Anything else
+/// raises a `TypeError`.
+#[pyfunction]
+#[pyo3(signature = (image, x_components = 4, y_components = 3, *, width = None, height = None))]
+fn encode(
+    py: Python<'_>,
+    image: &Bound<'_, PyAny>,
+    x_components: u32,
+    y_components: u32,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> PyResult<String> {
+    let (pixels, width, height) = match image.extract::<PyBackedBytes>() {
+        Ok(pixels) => match (width, height) {
+            (Some(width), Some(height)) => (pixels, width, height),
+            _ => {
+                return Err(PyTypeError::new_err(
+                    "width and height are required when encoding raw pixels",
+                ))
+            }
+        },
+        Err(_) if image.hasattr("convert")? && image.hasattr("size")? => {
+            let image = image.call_method1("convert", ("RGBA",))?;
+            let (width, height) = image.getattr("size")?.extract()?;
+            (image.call_method0("tobytes")?.extract()?, width, height)
+        }
+        Err(_) => {
+            return Err(PyTypeError::new_err(
+                "expected a PIL image, bytes or a bytearray",
+            ))
+        }
+    };
+
+    py.detach(|| blurhash_rs::encode(x_components, y_components, width, height, &pixels))
+        .map_err(to_py_err)
+}
+
+/// Decodes a blurhash to a `width` by `height` RGBA8 image.
+///
+/// Returns the pixels as `bytes`, or, with `numpy=True`, as a `uint8` array of shape
+/// `(height, width, 4)`. The punch parameter can be used to de- or increase the contrast of the
+/// resulting image.
+#[pyfunction]
+#[pyo3(signature = (blurhash, width, height, punch = 1.0, *, numpy = false))]
+fn decode(
+    py: Python<'_>,
+    blurhash: &str,
+    width: u32,
+    height: u32,
+    punch: f32,
+    numpy: bool,
+) -> PyResult<Py<PyAny>> {
+    let pixels = py
+        .detach(|| blurhash_rs::decode(blurhash, width, height, punch))
+        .map_err(to_py_err)?;
+
+    if numpy {
+        // A bytearray keeps the resulting array writable.
+        let array = py
+            .import("numpy")?
+            .call_method1("frombuffer", (PyByteArray::new(py, &pixels), "uint8"))?
+            .call_method1("reshape", (height, width, 4))?;
+        Ok(array.unbind())
+    } else {
+        Ok(PyBytes::new(py, &pixels).into_any().unbind())
+    }
+}
+
+/// Checks that a blurhash is well-formed, without decoding it.
+///
+/// Returns the number of horizontal and vertical components, or raises a `ValueError` that
+/// describes the first problem.
+#[pyfunction]
+fn validate(blurhash: &str) -> PyResult<(u32, u32)> {
+    let info = blurhash_rs::validate(blurhash).map_err(to_py_err)?;
+    Ok((info.components_x, info.components_y))
+}
+
+#[pymodule]
+fn blurhash(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(encode, m)?)?;
+    m.add_function(wrap_pyfunction!(decode, m)?)?;
+    m.add_function(wrap_pyfunction!(validate, m)?)?;
+    Ok(())
+}
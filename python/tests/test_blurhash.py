@@ -0,0 +1,75 @@
+from concurrent.futures import ThreadPoolExecutor
+from pathlib import Path
+
+import blurhash
+import numpy as np
+import pytest
+from PIL import Image
+
+DATA = Path(__file__).parents[2] / "data"
+OCTOCAT = "LNAdAqj[00aymkj[TKay9}ay-Sj["
+
+
+def assert_approximately_equal(result, target):
+    # The reference images were made with blurhash-python, which rounds slightly differently: the
+    # octocat is off by up to 9 in a few pixels.
+    error = np.abs(result.astype(np.int16) - target.astype(np.int16))
+    assert error.mean() <= 1
+    assert error.max() <= 10
+
+
+@pytest.mark.parametrize("name", ["octocat", "wikipedia_logo"])
+def test_reference_images(name):
+    with Image.open(DATA / f"{name}.png") as image:
+        hash = blurhash.encode(image, x_components=4, y_components=3)
+        width, height = image.size
+
+    with Image.open(DATA / f"{name}_blurred.png") as target:
+        target = np.asarray(target.convert("RGBA"))
+
+    result = blurhash.decode(hash, width, height, numpy=True)
+    assert result.shape == (height, width, 4)
+    assert_approximately_equal(result, target)
+
+
+def test_raw_pixels():
+    with Image.open(DATA / "octocat.png") as image:
+        image = image.convert("RGBA")
+        pixels = image.tobytes()
+        width, height = image.size
+
+    assert blurhash.encode(pixels, 4, 3, width=width, height=height) == OCTOCAT
+    assert blurhash.encode(bytearray(pixels), 4, 3, width=width, height=height) == OCTOCAT
+    assert blurhash.encode(image) == OCTOCAT
+
+    with pytest.raises(TypeError):
+        blurhash.encode(pixels, 4, 3)
+    # Other buffers have a tobytes method too, but are not images.
+    for other in [memoryview(pixels), np.frombuffer(pixels, np.uint8)]:
+        with pytest.raises(TypeError):
+            blurhash.encode(other, 4, 3, width=width, height=height)
+
+
+def test_decode_bytes():
+    pixels = blurhash.decode(OCTOCAT, 32, 24, 1.2)
+    assert isinstance(pixels, bytes)
+    assert pixels == blurhash.decode(OCTOCAT, 32, 24, 1.2, numpy=True).tobytes()
+
+
+def test_validate():
+    assert blurhash.validate(OCTOCAT) == (4, 3)
+
+    with pytest.raises(ValueError, match="at least 6 characters"):
+        blurhash.validate("LNAdA")
+    with pytest.raises(ValueError):
+        blurhash.decode(OCTOCAT[:-1], 32, 32)
+
+
+def test_threads():
+    hashes = [OCTOCAT, "LGF5]+Yk^6#M@-5c,1J5@[or[Q6.", "L6PZfSi_.AyE_3t7t7R**0o#DgR4"]
+    expected = [blurhash.decode(hash, 64, 64) for hash in hashes]
+
+    with ThreadPoolExecutor(4) as executor:
+        results = list(executor.map(lambda hash: blurhash.decode(hash, 64, 64), hashes * 8))
+
+    assert results == expected * 8
@@ -0,0 +1,209 @@
+//! Choosing component counts by how well the blurhash reconstructs the image.
+use crate::downsample::Downsampled;
+use crate::util::{linear_to_srgb_f32, sqrt};
+use crate::view::check_rgba_len;
+use crate::{auto_components, Blurhash, EncodeOptions, Error, ImageView};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// The most pixels along either edge at which reconstructions are compared with the image.
+const WORKING_SIZE: u32 = 32;
+
+/// The outcome of [encode_adaptive][crate::encode_adaptive].
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct AdaptiveEncoding {
+    /// The chosen blurhash.
+    pub blurhash: String,
+    /// The chosen `(components_x, components_y)`.
+    pub components: (u32, u32),
+    /// Every size that was tried, from the shortest hash to the longest.
+    pub attempts: Vec<Attempt>,
+}
+
+/// A single size that [encode_adaptive][crate::encode_adaptive] tried.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Attempt {
+    /// The `(components_x, components_y)` that were tried.
+    pub components: (u32, u32),
+    /// The root-mean-square difference between the decoded blurhash and the image, in 8-bit sRGB
+    /// levels.
+    pub error: f32,
+}
+
+/// Tries increasingly detailed blurhashes, and returns the shortest one whose reconstruction error
+/// is at most `max_error`, or the most accurate one if none is.
+///
+/// The candidates follow the aspect ratio of the image, as in [auto_components]. Every candidate
+/// is decoded to a small image, which is compared with the image box-filtered down to the same
+/// size. More components do not always mean a smaller error, because every added component is
+/// quantised, so the report in [AdaptiveEncoding::attempts] lists the error of every size tried.
+///
+/// ```
+/// // A flat image needs no more than a single component.
+/// let pixels = vec![128; 64 * 48 * 4];
+/// let adaptive = blurhash::encode_adaptive(64, 48, &pixels, 2.).unwrap();
+///
+/// assert_eq!(adaptive.components, (1, 1));
+/// assert_eq!(adaptive.attempts.len(), 1);
+/// ```
+pub fn encode_adaptive(
+    width: u32,
+    height: u32,
+    rgba_image: &[u8],
+    max_error: f32,
+) -> Result<AdaptiveEncoding, Error> {
+    check_rgba_len(rgba_image, width, height)?;
+
+    let mut candidates = Vec::new();
+    for budget in 1..=9 * 9 {
+        let components = auto_components(width, height, budget)?;
+        if !candidates.contains(&components) {
+            candidates.push(components);
+        }
+    }
+    candidates.sort_by_key(|&(x, y)| x * y);
+
+    // The factor of every component is independent of how many there are, so a single projection
+    // onto the largest basis serves every candidate.
+    let image = ImageView::new(rgba_image, width, height);
+    let (max_x, max_y) = candidates.iter().fold((1, 1), |(max_x, max_y), &(x, y)| {
+        (max_x.max(x), max_y.max(y))
+    });
+    let factors = crate::multiply_basis_functions(max_x, max_y, &image, &EncodeOptions::new());
+
+    let target = Downsampled::new(&image, Default::default(), WORKING_SIZE);
+    let (target_width, target_height) = (target.width() as u32, target.height() as u32);
+    let target: Vec<[f32; 3]> = target
+        .cells()
+        .iter()
+        .map(|&[r, g, b, weight]| [r, g, b].map(|c| linear_to_srgb_f32(c / weight) * 255.))
+        .collect();
+
+    let mut attempts = Vec::new();
+    let mut best: Option<(Blurhash, f32)> = None;
+    for (components_x, components_y) in candidates {
+        let factors: Vec<[f32; 3]> = factors
+            .chunks_exact(max_x as usize)
+            .take(components_y as usize)
+            .flat_map(|row| &row[..components_x as usize])
+            .copied()
+            .collect();
+        let blurhash = Blurhash::from_factors(components_x, components_y, &factors);
+
+        let pixels = blurhash.decode(target_width, target_height, 1.)?;
+        let squared_error: f32 = pixels
+            .chunks_exact(4)
+            .zip(&target)
+            .flat_map(|(pixel, target)| (0..3).map(move |c| pixel[c] as f32 - target[c]))
+            .map(|difference| difference * difference)
+            .sum();
+        let error = sqrt(squared_error / (target.len() * 3) as f32);
+
+        attempts.push(Attempt {
+            components: (components_x, components_y),
+            error,
+        });
+
+        let accepted = error <= max_error;
+        if accepted
+            || best
+                .as_ref()
+                .is_none_or(|&(_, best_error)| error < best_error)
+        {
+            best = Some((blurhash, error));
+        }
+        if accepted {
+            break;
+        }
+    }
+
+    let (blurhash, _) = best.expect("there is always at least one candidate");
+    Ok(AdaptiveEncoding {
+        blurhash: blurhash.to_string(),
+        components: blurhash.components(),
+        attempts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encode_adaptive;
+    use crate::{encode, Error};
+    use image::GenericImageView;
+
+    #[test]
+    fn gradient_needs_horizontal_components() {
+        let (width, height) = (120, 40);
+        let pixels: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let x = (i % width * 255 / (width - 1)) as u8;
+                [x, 255 - x, 128, 255]
+            })
+            .collect();
+
+        let adaptive = encode_adaptive(width, height, &pixels, 8.).unwrap();
+        let (components_x, components_y) = adaptive.components;
+        assert!(
+            components_x > 1 && components_y < components_x,
+            "{:?}",
+            adaptive
+        );
+        assert!(components_x * components_y < 9 * 3, "{:?}", adaptive);
+        assert_eq!(
+            adaptive.blurhash,
+            encode(components_x, components_y, width, height, &pixels).unwrap()
+        );
+
+        let (last, rest) = adaptive.attempts.split_last().unwrap();
+        assert_eq!(last.components, adaptive.components);
+        assert!(last.error <= 8.);
+        assert!(rest.iter().all(|attempt| attempt.error > 8.));
+    }
+
+    #[test]
+    fn unreachable_threshold_picks_the_most_accurate() {
+        let img = image::open("data/octocat.png").unwrap();
+        let (width, height) = img.dimensions();
+        let img = img.to_rgba8();
+
+        let adaptive = encode_adaptive(width, height, &img, 0.).unwrap();
+        let attempts = &adaptive.attempts;
+        assert_eq!(attempts.first().unwrap().components, (1, 1));
+        assert_eq!(attempts.last().unwrap().components, (9, 9));
+        assert!(attempts
+            .windows(2)
+            .all(|pair| pair[0].components.0 * pair[0].components.1
+                < pair[1].components.0 * pair[1].components.1));
+
+        let best = attempts
+            .iter()
+            .min_by(|a, b| a.error.total_cmp(&b.error))
+            .unwrap();
+        let (components_x, components_y) = best.components;
+        assert_eq!(adaptive.components, best.components);
+        assert_eq!(
+            adaptive.blurhash,
+            encode(components_x, components_y, width, height, &img).unwrap()
+        );
+    }
+
+    #[test]
+    fn bad_input() {
+        assert_eq!(
+            encode_adaptive(4, 4, &[0; 4 * 4 * 3], 1.),
+            Err(Error::BufferSizeMismatch {
+                expected: 4 * 4 * 4,
+                actual: 4 * 4 * 3
+            })
+        );
+        assert_eq!(
+            encode_adaptive(0, 4, &[], 1.),
+            Err(Error::InvalidDimensions {
+                width: 0,
+                height: 4
+            })
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::Error;
+use alloc::string::String;
 
 include!(concat!(env!("OUT_DIR"), "/base83_lookup.rs"));
 
@@ -9,20 +9,21 @@ pub fn encode_into(value: u32, length: u32, s: &mut String) {
     }
 }
 
-pub fn decode(str: &str) -> Result<u64, Error> {
+/// Decodes a base83 number, or returns the position of the first byte that is not a digit.
+pub fn decode(str: &str) -> Result<u64, usize> {
     // log_83(2^64) = 10.03
     if str.len() > 10 {
         panic!("base83::decode can only process strings up to 10 characters");
     }
     let mut value = 0;
 
-    for byte in str.as_bytes() {
+    for (position, byte) in str.as_bytes().iter().enumerate() {
         if *byte as usize >= CHARACTERS_INV.len() {
-            return Err(Error::InvalidBase83(*byte));
+            return Err(position);
         }
         let digit = CHARACTERS_INV[*byte as usize];
         if digit == CHARACTERS_INV_INVALID {
-            return Err(Error::InvalidBase83(*byte));
+            return Err(position);
         }
         value = value * 83 + digit as u64;
     }
@@ -30,6 +31,13 @@ pub fn decode(str: &str) -> Result<u64, Error> {
     Ok(value)
 }
 
+/// A strategy for strings of `len` base83 digits.
+#[cfg(test)]
+pub(crate) fn base83_string(len: usize) -> impl proptest::strategy::Strategy<Value = String> {
+    let reg = format!("([A-Za-z0-9#$%*+,-.:;=?@\\[\\]^_{{|}}~]){{{len}}}");
+    proptest::string::string_regex(&reg).unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use super::{decode, encode_into};
@@ -54,7 +62,8 @@ mod tests {
 
     #[test]
     fn decode83_too_large() {
-        assert!(decode("€").is_err());
+        assert_eq!(decode("€"), Err(0));
+        assert_eq!(decode("~\""), Err(1));
     }
 
     #[test]
@@ -0,0 +1,285 @@
+//! Hashing whole directory trees into a manifest.
+use clap::ValueEnum;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+/// The layout of a manifest file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ManifestFormat {
+    /// One JSON object per line.
+    Jsonl,
+    /// Comma-separated values, with a header row.
+    Csv,
+}
+
+impl ManifestFormat {
+    /// Guesses the format from the extension of the manifest, defaulting to JSON Lines.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("csv") => ManifestFormat::Csv,
+            _ => ManifestFormat::Jsonl,
+        }
+    }
+}
+
+pub struct Batch {
+    pub root: PathBuf,
+    pub manifest: PathBuf,
+    pub format: ManifestFormat,
+    pub components_x: u32,
+    pub components_y: u32,
+    pub jobs: usize,
+}
+
+/// A single line of the manifest.
+struct Entry {
+    path: String,
+    width: u32,
+    height: u32,
+    hash: String,
+    average_color: [u8; 3],
+}
+
+impl Entry {
+    fn average_color_hex(&self) -> String {
+        let [r, g, b] = self.average_color;
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+/// The outcome of a batch run.
+pub struct Summary {
+    pub hashed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+impl Batch {
+    /// Hashes every image below `root` that is not in the manifest yet, and appends it there.
+    ///
+    /// Files that cannot be read or decoded are reported on stderr, and do not stop the run.
+    pub fn run(&self) -> Result<Summary, Box<dyn Error>> {
+        let (done, complete) = self.read_manifest()?;
+
+        let mut failed = 0;
+        let mut files = Vec::new();
+        walk(&self.root, &mut files, &mut |path, e| {
+            eprintln!("error: {}: {}", path.display(), e);
+            failed += 1;
+        });
+
+        let total = files.len();
+        let files: Vec<(PathBuf, String)> = files
+            .into_iter()
+            .map(|path| {
+                let relative = path.strip_prefix(&self.root).unwrap_or(&path);
+                let relative = relative.to_string_lossy().into_owned();
+                (path, relative)
+            })
+            .filter(|(_, relative)| !done.contains(relative))
+            .collect();
+        let skipped = total - files.len();
+
+        let mut writer = self.open_manifest(complete)?;
+        let mut hashed = 0;
+
+        let next = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
+        std::thread::scope(|scope| -> Result<(), Box<dyn Error>> {
+            for _ in 0..self.jobs.max(1) {
+                let sender = sender.clone();
+                let (files, next) = (&files, &next);
+                scope.spawn(move || {
+                    while let Some((path, relative)) =
+                        files.get(next.fetch_add(1, Ordering::Relaxed))
+                    {
+                        let result = self.hash(path, relative);
+                        if sender.send((path, result)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            for (path, result) in receiver {
+                match result {
+                    Ok(entry) => {
+                        writer.write(&entry)?;
+                        hashed += 1;
+                    }
+                    Err(e) => {
+                        eprintln!("error: {}: {}", path.display(), e);
+                        failed += 1;
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(Summary {
+            hashed,
+            skipped,
+            failed,
+        })
+    }
+
+    fn hash(&self, path: &Path, relative: &str) -> Result<Entry, Box<dyn Error + Send + Sync>> {
+        let image = image::open(path)?.to_rgba8();
+        let hash = blurhash::encode_image(self.components_x, self.components_y, &image)?;
+        let average_color = blurhash::average_color(&hash)?;
+
+        Ok(Entry {
+            path: relative.to_owned(),
+            width: image.width(),
+            height: image.height(),
+            hash,
+            average_color,
+        })
+    }
+
+    /// Collects the paths that an earlier run already wrote to the manifest, along with the length
+    /// of the part of the manifest that holds whole entries.
+    ///
+    /// An interrupted run can leave an incomplete entry behind, without its line break. It is not
+    /// counted as done, so its file is hashed again, and [Batch::open_manifest] cuts it off.
+    fn read_manifest(&self) -> Result<(HashSet<String>, u64), Box<dyn Error>> {
+        let contents = match std::fs::read(&self.manifest) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((HashSet::new(), 0)),
+            Err(e) => return Err(e.into()),
+        };
+        let complete = contents
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |end| end + 1);
+        let contents = &contents[..complete];
+
+        let mut done = HashSet::new();
+        match self.format {
+            ManifestFormat::Jsonl => {
+                for line in contents.split(|&byte| byte == b'\n') {
+                    if line.iter().all(u8::is_ascii_whitespace) {
+                        continue;
+                    }
+                    let value: serde_json::Value = serde_json::from_slice(line)?;
+                    if let Some(path) = value.get("path").and_then(|path| path.as_str()) {
+                        done.insert(path.to_owned());
+                    }
+                }
+            }
+            ManifestFormat::Csv if contents.is_empty() => {}
+            ManifestFormat::Csv => {
+                let mut reader = csv::Reader::from_reader(contents);
+                let column = reader
+                    .headers()?
+                    .iter()
+                    .position(|header| header == "path")
+                    .ok_or("manifest has no path column")?;
+                for record in reader.records() {
+                    if let Some(path) = record?.get(column) {
+                        done.insert(path.to_owned());
+                    }
+                }
+            }
+        }
+        Ok((done, complete as u64))
+    }
+
+    /// Opens the manifest for appending, after cutting it off at `complete` bytes.
+    fn open_manifest(&self, complete: u64) -> Result<ManifestWriter, Box<dyn Error>> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&self.manifest)?;
+        file.set_len(complete)?;
+        file.seek(SeekFrom::End(0))?;
+        let is_empty = complete == 0;
+
+        Ok(match self.format {
+            ManifestFormat::Jsonl => ManifestWriter::Jsonl(BufWriter::new(file)),
+            ManifestFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(file);
+                if is_empty {
+                    writer.write_record(["path", "width", "height", "hash", "average_color"])?;
+                }
+                ManifestWriter::Csv(Box::new(writer))
+            }
+        })
+    }
+}
+
+enum ManifestWriter {
+    Jsonl(BufWriter<File>),
+    Csv(Box<csv::Writer<File>>),
+}
+
+impl ManifestWriter {
+    /// Appends an entry, and flushes it, so that an interrupted run leaves whole lines behind.
+    fn write(&mut self, entry: &Entry) -> Result<(), Box<dyn Error>> {
+        match self {
+            ManifestWriter::Jsonl(writer) => {
+                let line = serde_json::json!({
+                    "path": entry.path,
+                    "width": entry.width,
+                    "height": entry.height,
+                    "hash": entry.hash,
+                    "average_color": entry.average_color_hex(),
+                });
+                writeln!(writer, "{}", line)?;
+                writer.flush()?;
+            }
+            ManifestWriter::Csv(writer) => {
+                writer.write_record([
+                    entry.path.as_str(),
+                    &entry.width.to_string(),
+                    &entry.height.to_string(),
+                    &entry.hash,
+                    &entry.average_color_hex(),
+                ])?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Recursively collects the files below `dir` that look like images, in a stable order.
+///
+/// Symbolic links to directories are not followed, so a link cycle cannot trap the walk.
+fn walk(dir: &Path, files: &mut Vec<PathBuf>, on_error: &mut dyn FnMut(&Path, std::io::Error)) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return on_error(dir, e),
+    };
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                on_error(dir, e);
+                continue;
+            }
+        };
+        match entry.file_type() {
+            Ok(file_type) => paths.push((entry.path(), file_type.is_dir())),
+            Err(e) => on_error(&entry.path(), e),
+        }
+    }
+    paths.sort();
+
+    for (path, is_dir) in paths {
+        if is_dir {
+            walk(&path, files, on_error);
+        } else if image::ImageFormat::from_path(&path).is_ok() {
+            files.push(path);
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Command-line interface for encoding, decoding and inspecting blurhashes.
+mod batch;
+
+use batch::{Batch, ManifestFormat};
+use blurhash::Blurhash;
+use clap::{Parser, Subcommand};
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Calculate the blurhash of an image.
+    Encode {
+        /// The image to encode.
+        image: PathBuf,
+        /// The number of horizontal components, 1-9.
+        #[arg(short = 'x', default_value_t = 4)]
+        components_x: u32,
+        /// The number of vertical components, 1-9.
+        #[arg(short = 'y', default_value_t = 3)]
+        components_y: u32,
+    },
+    /// Render a blurhash to an image file.
+    #[command(disable_help_flag = true)]
+    Decode {
+        /// The blurhash to decode.
+        blurhash: String,
+        /// The width of the output image, in pixels.
+        #[arg(short = 'w', long, default_value_t = 32)]
+        width: u32,
+        /// The height of the output image, in pixels.
+        #[arg(short = 'h', long, default_value_t = 32)]
+        height: u32,
+        /// The output image. Its format is derived from the file extension.
+        #[arg(short = 'o', long)]
+        output: PathBuf,
+        /// Increases or decreases the contrast of the output.
+        #[arg(long, default_value_t = 1.0)]
+        punch: f32,
+        /// Print help.
+        #[arg(long, action = clap::ArgAction::Help)]
+        help: Option<bool>,
+    },
+    /// Print the components, average color and maximum AC value of a blurhash.
+    Inspect {
+        /// The blurhash to inspect.
+        blurhash: String,
+        /// The image that the blurhash was made from. If given, also print how faithfully the
+        /// blurhash represents it, as PSNR, SSIM and mean CIEDE2000 color difference.
+        #[arg(long)]
+        image: Option<PathBuf>,
+        /// Compare with the image scaled down to this many pixels on its longest edge, rather than
+        /// at full size.
+        #[arg(long, requires = "image")]
+        size: Option<u32>,
+    },
+    /// Hash every image in a directory tree into a manifest.
+    ///
+    /// Images that are already listed in the manifest are skipped, so an interrupted run can be
+    /// resumed by running the same command again.
+    Batch {
+        /// The directory to walk.
+        root: PathBuf,
+        /// The manifest to append to.
+        #[arg(short = 'o', long)]
+        output: PathBuf,
+        /// The manifest format. Defaults to CSV for `.csv` files, and to JSON Lines otherwise.
+        #[arg(long, value_enum)]
+        format: Option<ManifestFormat>,
+        /// The number of horizontal components, 1-9.
+        #[arg(short = 'x', default_value_t = 4)]
+        components_x: u32,
+        /// The number of vertical components, 1-9.
+        #[arg(short = 'y', default_value_t = 3)]
+        components_y: u32,
+        /// The number of images to hash in parallel. Defaults to the number of CPUs.
+        #[arg(short = 'j', long)]
+        jobs: Option<usize>,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(command: Command) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Encode {
+            image,
+            components_x,
+            components_y,
+        } => {
+            let image = image::open(image)?.to_rgba8();
+            println!(
+                "{}",
+                blurhash::encode_image(components_x, components_y, &image)?
+            );
+        }
+        Command::Decode {
+            blurhash,
+            width,
+            height,
+            output,
+            punch,
+            ..
+        } => {
+            let image = blurhash::decode_image(&blurhash, width, height, punch)?;
+            image.save(output)?;
+        }
+        Command::Inspect {
+            blurhash: hash,
+            image,
+            size,
+        } => {
+            let blurhash: Blurhash = hash.parse()?;
+            let (components_x, components_y) = blurhash.components();
+            let [r, g, b] = blurhash.average_color()?;
+
+            println!("components: {}x{}", components_x, components_y);
+            println!("average color: #{:02x}{:02x}{:02x}", r, g, b);
+            println!(
+                "maximum AC: {} ({:.4})",
+                blurhash.quantised_maximum_value(),
+                blurhash.maximum_value()
+            );
+            println!(
+                "relative luminance: {:.4} ({})",
+                blurhash.relative_luminance(),
+                if blurhash.is_dark() { "dark" } else { "light" }
+            );
+
+            if let Some(image) = image {
+                let image = image::open(image)?.to_rgba8();
+                let metrics =
+                    blurhash::metrics::measure(&hash, image.width(), image.height(), &image, size)?;
+                println!("PSNR: {:.2} dB", metrics.psnr);
+                println!("SSIM: {:.4}", metrics.ssim);
+                println!("mean ΔE2000: {:.2}", metrics.delta_e);
+            }
+        }
+        Command::Batch {
+            root,
+            output,
+            format,
+            components_x,
+            components_y,
+            jobs,
+        } => {
+            let batch = Batch {
+                format: format.unwrap_or_else(|| ManifestFormat::from_path(&output)),
+                root,
+                manifest: output,
+                components_x,
+                components_y,
+                jobs: jobs.unwrap_or_else(|| {
+                    std::thread::available_parallelism().map_or(1, |jobs| jobs.get())
+                }),
+            };
+            let summary = batch.run()?;
+            eprintln!(
+                "hashed {}, skipped {} already in the manifest, {} failed",
+                summary.hashed, summary.skipped, summary.failed
+            );
+            if summary.failed > 0 {
+                return Err(format!("{} files could not be hashed", summary.failed).into());
+            }
+        }
+    }
+    Ok(())
+}
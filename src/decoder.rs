@@ -0,0 +1,552 @@
+use crate::util::cos;
+use crate::view::{check_rgba_len, rgba_len};
+use crate::{Blurhash, Error, PixelFormat};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::f32::consts::PI;
+
+/// A reusable context for decoding many blurhashes to images of the same size.
+///
+/// The decoder keeps the cosine tables for its size, along with its scratch buffers, between
+/// decodes. Once it has seen the largest component count it is going to decode, decoding into an
+/// existing buffer does not allocate anymore.
+///
+/// ```
+/// use blurhash::{Blurhash, Decoder};
+///
+/// let mut decoder = Decoder::new(32, 32);
+/// let mut pixels = vec![0; 32 * 32 * 4];
+///
+/// for hash in ["LNAdAqj[00aymkj[TKay9}ay-Sj[", "LBAdAqof00WCqZj[PDay0.WB}pof"] {
+///     let blurhash: Blurhash = hash.parse().unwrap();
+///     decoder.decode_into(&mut pixels, &blurhash, 1.0).unwrap();
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Decoder {
+    width: u32,
+    height: u32,
+    /// The number of components `cos_i_pi_x_over_width` holds a row of `width` cosines for.
+    num_x: usize,
+    /// The number of components `cos_j_pi_y_over_height` holds for every `y`.
+    num_y: usize,
+    cos_i_pi_x_over_width: Vec<f32>,
+    cos_j_pi_y_over_height: Vec<f32>,
+    /// The red, green and blue channels of a row, one after the other.
+    planes: Vec<f32>,
+    linear_row: Vec<[f32; 3]>,
+}
+
+impl Decoder {
+    /// Creates a decoder for images of the specified size.
+    pub fn new(width: u32, height: u32) -> Self {
+        Decoder {
+            width,
+            height,
+            num_x: 0,
+            num_y: 0,
+            cos_i_pi_x_over_width: Vec::new(),
+            cos_j_pi_y_over_height: Vec::new(),
+            planes: Vec::new(),
+            linear_row: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Decodes the given blurhash to an RGBA8 image.
+    ///
+    /// The punch parameter can be used to de- or increase the contrast of the
+    /// resulting image.
+    pub fn decode(&mut self, blurhash: &Blurhash, punch: f32) -> Result<Vec<u8>, Error> {
+        let mut pixels = vec![0; rgba_len(self.width, self.height)?];
+        self.decode_into(&mut pixels, blurhash, punch)
+            .map(|()| pixels)
+    }
+
+    /// Decodes the given blurhash to an RGBA8 image into an existing buffer.
+    ///
+    /// The punch parameter can be used to de- or increase the contrast of the
+    /// resulting image.
+    pub fn decode_into(
+        &mut self,
+        pixels: &mut [u8],
+        blurhash: &Blurhash,
+        punch: f32,
+    ) -> Result<(), Error> {
+        check_rgba_len(pixels, self.width, self.height)?;
+
+        let stride = self.width as usize * 4;
+        self.decode_into_layout(pixels, stride, PixelFormat::Rgba8, blurhash, punch)
+    }
+
+    /// Decodes the given blurhash into an existing buffer of pixels in the given format, whose
+    /// rows start `stride` bytes apart.
+    ///
+    /// See [decode_into_layout][crate::decode_into_layout].
+    pub fn decode_into_layout(
+        &mut self,
+        pixels: &mut [u8],
+        stride: usize,
+        format: PixelFormat,
+        blurhash: &Blurhash,
+        punch: f32,
+    ) -> Result<(), Error> {
+        check_layout(pixels, stride, format, self.width, self.height)?;
+
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let bytes_per_row = width * format.bytes_per_pixel();
+
+        // The scratch buffers are only sized now that the layout shows that a row of pixels fits
+        // in memory.
+        self.planes.resize(width * 3, 0.);
+        self.linear_row.resize(width, [0.; 3]);
+
+        let (num_x, num_y) = blurhash.components();
+        let (num_x, num_y) = (num_x as usize, num_y as usize);
+        self.precompute_cosines(num_x, num_y);
+
+        let colors = blurhash.colors(punch);
+        let colors = &colors[..num_x * num_y];
+
+        let Decoder {
+            num_x: table_x,
+            num_y: table_y,
+            cos_i_pi_x_over_width,
+            cos_j_pi_y_over_height,
+            planes,
+            linear_row,
+            ..
+        } = self;
+        let (table_x, table_y) = (*table_x, *table_y);
+
+        // Hint to the optimizer that the length of the slices is correct
+        assert!(height * table_y == cos_j_pi_y_over_height.len());
+        assert!(width * table_x == cos_i_pi_x_over_width.len());
+
+        for y in 0..height {
+            let pixels = &mut pixels[y * stride..][..bytes_per_row];
+
+            let cos_j_pi_y_over_height = &cos_j_pi_y_over_height[y * table_y..][..num_y];
+            let (red, planes) = planes.split_at_mut(width);
+            let (green, blue) = planes.split_at_mut(width);
+            sum_row(
+                colors,
+                cos_j_pi_y_over_height,
+                cos_i_pi_x_over_width.chunks_exact(width),
+                [red, green, blue],
+                linear_row,
+            );
+
+            format.write_row(linear_row, pixels);
+        }
+        Ok(())
+    }
+
+    /// Makes sure the cosine tables hold at least `num_x` and `num_y` components.
+    fn precompute_cosines(&mut self, num_x: usize, num_y: usize) {
+        if num_x > self.num_x {
+            let width = self.width;
+            self.cos_i_pi_x_over_width.clear();
+            for i in 0..num_x {
+                self.cos_i_pi_x_over_width
+                    .extend((0..width).map(|x| horizontal_cosine(x, i, width)));
+            }
+            self.num_x = num_x;
+        }
+
+        if num_y > self.num_y {
+            self.cos_j_pi_y_over_height.clear();
+            for y in 0..self.height {
+                let mut cosines = [0.; MAX_COMPONENTS_Y];
+                vertical_cosines(y, self.height, &mut cosines[..num_y]);
+                self.cos_j_pi_y_over_height
+                    .extend_from_slice(&cosines[..num_y]);
+            }
+            self.num_y = num_y;
+        }
+    }
+}
+
+/// The most vertical components a blurhash can hold. Encoders stop at 9, but the size flags `}`
+/// and `~` stand for 10 rows, and decoding has always accepted them.
+pub(crate) const MAX_COMPONENTS_Y: usize = 10;
+
+/// The number of pixels [decode_into_layout] decodes at a time.
+pub(crate) const TILE_WIDTH: usize = 32;
+
+/// Decodes a blurhash without allocating, by working through the image in tiles of
+/// [TILE_WIDTH] columns, whose horizontal cosines fit on the stack.
+///
+/// This yields exactly the same pixels as [Decoder], which computes the same cosines once per
+/// image instead of once per tile.
+pub(crate) fn decode_into_layout(
+    pixels: &mut [u8],
+    stride: usize,
+    format: PixelFormat,
+    blurhash: &Blurhash,
+    width: u32,
+    height: u32,
+    punch: f32,
+) -> Result<(), Error> {
+    check_layout(pixels, stride, format, width, height)?;
+
+    let bytes_per_pixel = format.bytes_per_pixel();
+
+    let (num_x, num_y) = blurhash.components();
+    let (num_x, num_y) = (num_x as usize, num_y as usize);
+
+    let colors = blurhash.colors(punch);
+    let colors = &colors[..num_x * num_y];
+
+    let mut cos_i_pi_x_over_width = [[0.; TILE_WIDTH]; 9];
+    let mut cos_j_pi_y_over_height = [0.; MAX_COMPONENTS_Y];
+    let mut planes = [[0.; TILE_WIDTH]; 3];
+    let mut linear_tile = [[0.; 3]; TILE_WIDTH];
+
+    for tile_x in (0..width).step_by(TILE_WIDTH) {
+        let tile_width = (width - tile_x).min(TILE_WIDTH as u32) as usize;
+        for (i, cosines) in cos_i_pi_x_over_width[..num_x].iter_mut().enumerate() {
+            for (x, cosine) in (tile_x..).zip(&mut cosines[..tile_width]) {
+                *cosine = horizontal_cosine(x, i, width);
+            }
+        }
+
+        for y in 0..height {
+            let start = y as usize * stride + tile_x as usize * bytes_per_pixel;
+            let pixels = &mut pixels[start..][..tile_width * bytes_per_pixel];
+
+            vertical_cosines(y, height, &mut cos_j_pi_y_over_height[..num_y]);
+            let linear_tile = &mut linear_tile[..tile_width];
+            let [red, green, blue] = &mut planes;
+            sum_row(
+                colors,
+                &cos_j_pi_y_over_height[..num_y],
+                cos_i_pi_x_over_width
+                    .iter()
+                    .map(|cosines| &cosines[..tile_width]),
+                [
+                    &mut red[..tile_width],
+                    &mut green[..tile_width],
+                    &mut blue[..tile_width],
+                ],
+                linear_tile,
+            );
+
+            format.write_row(linear_tile, pixels);
+        }
+    }
+    Ok(())
+}
+
+/// Checks that `pixels` can hold a non-empty `width` by `height` image in the given format, whose
+/// rows start `stride` bytes apart.
+pub(crate) fn check_layout(
+    pixels: &[u8],
+    stride: usize,
+    format: PixelFormat,
+    width: u32,
+    height: u32,
+) -> Result<(), Error> {
+    if width == 0 || height == 0 {
+        return Err(Error::InvalidDimensions { width, height });
+    }
+
+    let bytes_per_row = width as usize * format.bytes_per_pixel();
+    if stride < bytes_per_row {
+        return Err(Error::StrideTooSmall {
+            stride,
+            minimum: bytes_per_row,
+        });
+    }
+    let expected = (height as usize - 1)
+        .saturating_mul(stride)
+        .saturating_add(bytes_per_row);
+    if pixels.len() < expected {
+        return Err(Error::BufferSizeMismatch {
+            expected,
+            actual: pixels.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Computes `cos(PI * x * i / width)`.
+fn horizontal_cosine(x: u32, i: usize, width: u32) -> f32 {
+    let pi_x_over_width = x as f32 * (PI / width as f32);
+    cos(pi_x_over_width * i as f32)
+}
+
+/// Computes `cos(PI * y * j / height)` for every component `j` in `cosines`.
+fn vertical_cosines(y: u32, height: u32, cosines: &mut [f32]) {
+    let pi_y_over_height = y as f32 * (PI / height as f32);
+    for (j, cosine) in cosines.iter_mut().enumerate() {
+        *cosine = cos(j as f32 * pi_y_over_height);
+    }
+}
+
+/// Sums every component at a run of pixels of one row, into `linear_row`.
+///
+/// `cos_i_pi_x_over_width` yields the cosines of every horizontal component across the run. Each
+/// pixel adds up its terms in the same order, and rounds them the same way, as evaluating it on
+/// its own would, so the output does not depend on how a row is split up. Only the pixels are
+/// swept innermost, one channel per plane of `planes`, which lets the compiler vectorize across
+/// them.
+fn sum_row<'a>(
+    colors: &[[f32; 3]],
+    cos_j_pi_y_over_height: &[f32],
+    cos_i_pi_x_over_width: impl Iterator<Item = &'a [f32]> + Clone,
+    planes: [&mut [f32]; 3],
+    linear_row: &mut [[f32; 3]],
+) {
+    let num_x = colors.len() / cos_j_pi_y_over_height.len();
+    let [red, green, blue] = planes;
+    red.fill(0.);
+    green.fill(0.);
+    blue.fill(0.);
+
+    for (cos_j, colors) in cos_j_pi_y_over_height.iter().zip(colors.chunks(num_x)) {
+        for (color, cos_i_pi_x_over_width) in colors.iter().zip(cos_i_pi_x_over_width.clone()) {
+            for (((red, green), blue), cos_i) in red
+                .iter_mut()
+                .zip(green.iter_mut())
+                .zip(blue.iter_mut())
+                .zip(cos_i_pi_x_over_width)
+            {
+                let basis = cos_i * cos_j;
+
+                *red += color[0] * basis;
+                *green += color[1] * basis;
+                *blue += color[2] * basis;
+            }
+        }
+    }
+
+    for (((pixel, red), green), blue) in linear_row.iter_mut().zip(red).zip(green).zip(blue) {
+        *pixel = [*red, *green, *blue];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Decoder;
+    use crate::{decode, Blurhash, Error, PixelFormat};
+    use proptest::prelude::*;
+    use std::f32::consts::PI;
+
+    /// The decoder before the components were summed a row at a time, which evaluates every basis
+    /// function at every pixel in turn.
+    fn decode_per_pixel(blurhash: &Blurhash, width: u32, height: u32, punch: f32) -> Vec<u8> {
+        let (num_x, num_y) = blurhash.components();
+        let (num_x, num_y) = (num_x as usize, num_y as usize);
+        let colors = blurhash.colors(punch);
+
+        let pi_over_width = PI / width as f32;
+        let pi_over_height = PI / height as f32;
+        let mut cos_i_pi_x_over_width = Vec::new();
+        for x in 0..width {
+            let pi_x_over_width = x as f32 * pi_over_width;
+            cos_i_pi_x_over_width.extend((0..num_x).map(|i| f32::cos(pi_x_over_width * i as f32)));
+        }
+        let mut cos_j_pi_y_over_height = Vec::new();
+        for y in 0..height {
+            let pi_y_over_height = y as f32 * pi_over_height;
+            cos_j_pi_y_over_height
+                .extend((0..num_y).map(|j| f32::cos(j as f32 * pi_y_over_height)));
+        }
+
+        let mut pixels = vec![0; width as usize * height as usize * 4];
+        let mut linear_row = vec![[0.; 3]; width as usize];
+        for (y, pixels) in pixels.chunks_mut(width as usize * 4).enumerate() {
+            for (x, pixel) in linear_row.iter_mut().enumerate() {
+                *pixel = [0.; 3];
+
+                let cos_j_pi_y_over_height = &cos_j_pi_y_over_height[y * num_y..][..num_y];
+                let cos_i_pi_x_over_width = &cos_i_pi_x_over_width[x * num_x..][..num_x];
+
+                for (cos_j, colors) in cos_j_pi_y_over_height.iter().zip(colors.chunks(num_x)) {
+                    for (cos_i, color) in cos_i_pi_x_over_width.iter().zip(colors.iter()) {
+                        let basis = cos_i * cos_j;
+
+                        pixel[0] += color[0] * basis;
+                        pixel[1] += color[1] * basis;
+                        pixel[2] += color[2] * basis;
+                    }
+                }
+            }
+
+            PixelFormat::Rgba8.write_row(&linear_row, pixels);
+        }
+        pixels
+    }
+
+    #[test]
+    fn reuse_across_component_counts() {
+        let image: Vec<u8> = (0..16 * 16 * 4).map(|i| (i * 7 % 256) as u8).collect();
+        let mut decoder = Decoder::new(23, 17);
+
+        for (components_x, components_y) in [(1, 1), (4, 3), (9, 9), (2, 7), (9, 1)] {
+            let hash = crate::encode(components_x, components_y, 16, 16, &image).unwrap();
+            let blurhash: Blurhash = hash.parse().unwrap();
+            assert_eq!(
+                decoder.decode(&blurhash, 1.).unwrap(),
+                decode(&hash, 23, 17, 1.).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn buffer_errors() {
+        let blurhash: Blurhash = "LNAdAqj[00aymkj[TKay9}ay-Sj[".parse().unwrap();
+        let mut pixels = vec![0; 10 * 10 * 4 + 1];
+
+        assert_eq!(
+            Decoder::new(10, 10).decode_into(&mut pixels, &blurhash, 1.),
+            Err(Error::BufferSizeMismatch {
+                expected: 400,
+                actual: 401
+            })
+        );
+        assert_eq!(
+            Decoder::new(10, 10).decode_into(&mut pixels[..399], &blurhash, 1.),
+            Err(Error::BufferSizeMismatch {
+                expected: 400,
+                actual: 399
+            })
+        );
+        // 2^31 * 2^31 * 4 would wrap around to 0.
+        assert_eq!(
+            blurhash.decode_into(&mut [], 1 << 31, 1 << 31, 1.),
+            Err(Error::BufferSizeMismatch {
+                expected: usize::MAX,
+                actual: 0
+            })
+        );
+        // Allocating a buffer of that length must not overflow either, and neither may one of
+        // 2^31 * 2^30 * 4 bytes, which fits in a usize but is larger than isize::MAX.
+        for (width, height) in [(1 << 31, 1 << 31), (1 << 31, 1 << 30)] {
+            assert_eq!(
+                blurhash.decode(width, height, 1.),
+                Err(Error::InvalidDimensions { width, height })
+            );
+            assert_eq!(
+                decode("LNAdAqj[00aymkj[TKay9}ay-Sj[", width, height, 1.),
+                Err(Error::InvalidDimensions { width, height })
+            );
+            assert_eq!(
+                Decoder::new(width, height).decode(&blurhash, 1.),
+                Err(Error::InvalidDimensions { width, height })
+            );
+        }
+        // Creating a decoder must not allocate for a size that the buffer turns out not to hold.
+        assert_eq!(
+            Decoder::new(u32::MAX, 1).decode_into(&mut [], &blurhash, 1.),
+            Err(Error::BufferSizeMismatch {
+                expected: (u32::MAX as usize).saturating_mul(4),
+                actual: 0
+            })
+        );
+        assert_eq!(
+            Decoder::new(0, 10).decode(&blurhash, 1.),
+            Err(Error::InvalidDimensions {
+                width: 0,
+                height: 10
+            })
+        );
+        assert_eq!(
+            Decoder::new(10, 10).decode_into_layout(
+                &mut pixels,
+                39,
+                PixelFormat::Rgba8,
+                &blurhash,
+                1.
+            ),
+            Err(Error::StrideTooSmall {
+                stride: 39,
+                minimum: 40
+            })
+        );
+        assert_eq!(
+            Decoder::new(10, 10).decode_into_layout(
+                &mut pixels,
+                usize::MAX,
+                PixelFormat::Rgba8,
+                &blurhash,
+                1.
+            ),
+            Err(Error::BufferSizeMismatch {
+                expected: usize::MAX,
+                actual: 401
+            })
+        );
+        assert!(Decoder::new(10, 10)
+            .decode_into_layout(&mut pixels, 44, PixelFormat::Rgb8, &blurhash, 1.)
+            .is_err());
+    }
+
+    #[test]
+    fn ten_rows() {
+        // The size flags "}" and "~" stand for 1x10 and 2x10 components.
+        for hash in [
+            "}".to_owned() + &"0".repeat(23),
+            "~".to_owned() + &"9".repeat(43),
+        ] {
+            let blurhash: Blurhash = hash.parse().unwrap();
+            assert_eq!(blurhash.components().1, 10);
+
+            let per_pixel = decode_per_pixel(&blurhash, 8, 12, 1.);
+            assert_eq!(decode(&hash, 8, 12, 1.).unwrap(), per_pixel);
+            assert_eq!(
+                Decoder::new(8, 12).decode(&blurhash, 1.).unwrap(),
+                per_pixel
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn heapless_matches_decoder(
+            hash in crate::hash::blurhashes(),
+            width in 1..100u32,
+            height in 1..20u32,
+        ) {
+            let blurhash: Blurhash = hash.parse().unwrap();
+
+            let mut pixels = vec![0; width as usize * height as usize * 4];
+            blurhash.decode_into(&mut pixels, width, height, 1.).unwrap();
+            prop_assert_eq!(pixels, Decoder::new(width, height).decode(&blurhash, 1.).unwrap());
+
+            let stride = width as usize * 3 + 5;
+            let mut tiled = vec![0; stride * height as usize];
+            let mut cached = tiled.clone();
+            blurhash
+                .decode_into_layout(&mut tiled, stride, PixelFormat::Rgb8, width, height, 1.)
+                .unwrap();
+            Decoder::new(width, height)
+                .decode_into_layout(&mut cached, stride, PixelFormat::Rgb8, &blurhash, 1.)
+                .unwrap();
+            prop_assert_eq!(tiled, cached);
+        }
+
+        #[test]
+        fn matches_per_pixel_decoder(
+            hash in crate::hash::blurhashes(),
+            width in 1..80u32,
+            height in 1..64u32,
+            punch in 0.5..2.0f32,
+        ) {
+            let blurhash: Blurhash = hash.parse().unwrap();
+
+            let per_pixel = decode_per_pixel(&blurhash, width, height, punch);
+            prop_assert_eq!(&Decoder::new(width, height).decode(&blurhash, punch).unwrap(), &per_pixel);
+            prop_assert_eq!(&decode(&hash, width, height, punch).unwrap(), &per_pixel);
+        }
+    }
+}
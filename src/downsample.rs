@@ -0,0 +1,114 @@
+//! Box-filtering an image down to a small working resolution before it is encoded.
+use crate::{AlphaMode, ImageView};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// An image that has been reduced to a grid of cells, each of which holds the sum of the linear,
+/// alpha-adjusted colors of the pixels it covers, followed by their total weight.
+///
+/// Keeping sums instead of averages means that cells of different sizes still count for the
+/// number of pixels they cover.
+pub(crate) struct Downsampled {
+    width: usize,
+    cells: Vec<[f32; 4]>,
+    centers_x: Vec<f32>,
+    centers_y: Vec<f32>,
+}
+
+impl Downsampled {
+    /// Reduces the image so that neither edge is longer than `size` cells.
+    ///
+    /// Each edge is reduced on its own, so that the short edge of a long and narrow image keeps as
+    /// much detail as the edges of a square one. An edge that is already short is left alone.
+    pub(crate) fn new(image: &ImageView, alpha: AlphaMode, size: u32) -> Self {
+        let (width, height) = (image.width(), image.height());
+        let size = size.max(1);
+        let cells_x = width.clamp(1, size) as usize;
+        let cells_y = height.clamp(1, size) as usize;
+
+        let (cell_of_x, centers_x) = partition(width as usize, cells_x);
+        let (_, centers_y) = partition(height as usize, cells_y);
+
+        let cell_row = |cell_y: usize| {
+            let rows = cell_y * height as usize / cells_y..(cell_y + 1) * height as usize / cells_y;
+            let mut cells = vec![[0.; 4]; cells_x];
+            let mut linear_row = vec![[0.; 4]; width as usize];
+            for y in rows {
+                image.format().linearize_row(image.row(y), &mut linear_row);
+                alpha.apply(&mut linear_row);
+
+                for (pixel, &cell_x) in linear_row.iter().zip(&cell_of_x) {
+                    let cell = &mut cells[cell_x];
+                    cell[0] += pixel[0];
+                    cell[1] += pixel[1];
+                    cell[2] += pixel[2];
+                    cell[3] += alpha.weight(pixel);
+                }
+            }
+            cells
+        };
+
+        #[cfg(feature = "rayon")]
+        let cells: Vec<[f32; 4]> = {
+            use rayon::prelude::*;
+            (0..cells_y)
+                .into_par_iter()
+                .flat_map_iter(cell_row)
+                .collect()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let cells: Vec<[f32; 4]> = (0..cells_y).flat_map(cell_row).collect();
+
+        Downsampled {
+            width: cells_x,
+            cells,
+            centers_x,
+            centers_y,
+        }
+    }
+
+    /// The number of columns of cells.
+    pub(crate) fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The number of rows of cells.
+    pub(crate) fn height(&self) -> usize {
+        self.centers_y.len()
+    }
+
+    /// The cells in row-major order.
+    pub(crate) fn cells(&self) -> &[[f32; 4]] {
+        &self.cells
+    }
+
+    /// The positions of the columns of cells, in pixels of the original image.
+    pub(crate) fn centers_x(&self) -> &[f32] {
+        &self.centers_x
+    }
+
+    /// The positions of the rows of cells, in pixels of the original image.
+    pub(crate) fn centers_y(&self) -> &[f32] {
+        &self.centers_y
+    }
+
+    /// Copies a row of cells into `row`, and returns its total weight.
+    pub(crate) fn row(&self, y: usize, row: &mut [[f32; 4]]) -> f64 {
+        row.copy_from_slice(&self.cells[y * self.width..][..self.width]);
+        row.iter().map(|cell| cell[3] as f64).sum()
+    }
+}
+
+/// Splits `len` pixels into `cells` runs of nearly equal length.
+///
+/// Returns the cell of every pixel, and the center of every cell.
+fn partition(len: usize, cells: usize) -> (Vec<usize>, Vec<f32>) {
+    let mut cell_of = Vec::with_capacity(len);
+    let mut centers = Vec::with_capacity(cells);
+    for cell in 0..cells {
+        let (start, end) = (cell * len / cells, (cell + 1) * len / cells);
+        cell_of.resize(end, cell);
+        centers.push((start + end - 1) as f32 / 2.);
+    }
+    (cell_of, centers)
+}
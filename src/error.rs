@@ -1,12 +1,96 @@
-use std::fmt;
+use alloc::format;
+use alloc::string::ToString;
+use core::fmt;
 
-#[derive(Debug)]
+/// A field of an encoded blurhash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Field {
+    /// The first character, which holds the number of components.
+    SizeFlag,
+    /// The second character, which holds the quantised maximum AC value.
+    MaximumValue,
+    /// The four characters that hold the average color.
+    Dc,
+    /// The two characters that hold the AC component with the given index, in row-major order
+    /// and starting at 0.
+    Ac(usize),
+}
+
+impl Field {
+    /// The offset of the first character of this field in a blurhash.
+    pub fn offset(self) -> usize {
+        match self {
+            Field::SizeFlag => 0,
+            Field::MaximumValue => 1,
+            Field::Dc => 2,
+            Field::Ac(index) => 6 + 2 * index,
+        }
+    }
+
+    /// The number of characters this field spans.
+    pub(crate) fn len(self) -> usize {
+        match self {
+            Field::SizeFlag | Field::MaximumValue => 1,
+            Field::Dc => 4,
+            Field::Ac(_) => 2,
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Field::SizeFlag => write!(f, "size flag"),
+            Field::MaximumValue => write!(f, "maximum AC value"),
+            Field::Dc => write!(f, "DC component"),
+            Field::Ac(index) => write!(f, "AC component {}", index),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Error {
     HashTooShort,
-    LengthMismatch { expected: usize, actual: usize },
-    InvalidAscii,
-    InvalidBase83(u8),
+    LengthMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// The blurhash contains a non-ASCII character, starting at byte `offset`.
+    InvalidAscii {
+        offset: usize,
+    },
+    /// The character at `offset` is not a base83 digit.
+    InvalidBase83 {
+        byte: u8,
+        offset: usize,
+        field: Field,
+    },
     ComponentsOutOfRange,
+    /// The value of the field starting at `offset` does not fit its range.
+    ValueOutOfRange {
+        offset: usize,
+        field: Field,
+    },
+    /// The pixel buffer holds `actual` bytes where `expected` are needed.
+    BufferSizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// The rows of the pixel buffer are closer together than the width of the image.
+    StrideTooSmall {
+        stride: usize,
+        minimum: usize,
+    },
+    /// The image is empty, or too large to fit in memory.
+    InvalidDimensions {
+        width: u32,
+        height: u32,
+    },
+    /// The budget leaves no room for even a single component.
+    InvalidBudget {
+        budget: u32,
+    },
 }
 
 impl fmt::Display for Error {
@@ -17,12 +101,42 @@ impl fmt::Display for Error {
                 "blurhash length mismatch: length is {} but it should be {}",
                 actual, expected
             ),
-            Error::InvalidBase83(byte) => format!("Invalid base83 character: {:?}", *byte as char),
-            Error::InvalidAscii => "blurhash must be valid ASCII".into(),
+            Error::InvalidBase83 {
+                byte,
+                offset,
+                field,
+            } => format!(
+                "Invalid base83 character: {:?} at offset {}, in the {}",
+                *byte as char, offset, field
+            ),
+            Error::InvalidAscii { offset } => {
+                format!(
+                    "blurhash must be valid ASCII, but is not at offset {}",
+                    offset
+                )
+            }
             Error::ComponentsOutOfRange => "blurhash must have between 1 and 9 components".into(),
+            Error::ValueOutOfRange { offset, field } => {
+                format!("the {} at offset {} is out of range", field, offset)
+            }
+            Error::BufferSizeMismatch { expected, actual } => format!(
+                "buffer length mismatch: length is {} but it should be {}",
+                actual, expected
+            ),
+            Error::StrideTooSmall { stride, minimum } => {
+                format!("stride is {} but rows are {} bytes long", stride, minimum)
+            }
+            Error::InvalidDimensions { width, height } => format!(
+                "image dimensions must be nonzero and fit in memory, but are {}x{}",
+                width, height
+            ),
+            Error::InvalidBudget { budget } => {
+                format!("component budget must be at least 1, but is {}", budget)
+            }
         };
         write!(f, "{}", message)
     }
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for Error {}
@@ -0,0 +1,167 @@
+//! A C ABI, for use from C, Swift, Kotlin and other languages with a C FFI.
+//!
+//! All buffers are allocated by the caller, and every function returns a [BlurhashStatus]. The
+//! matching header is `include/blurhash.h`, which is generated by `cbindgen`.
+use crate::{Blurhash, Error};
+use core::ffi::{c_char, CStr};
+
+/// The length of the longest blurhash, of 9 by 9 components, without the terminating NUL.
+pub const BLURHASH_MAX_LEN: usize = 4 + 2 * 9 * 9;
+
+/// The outcome of a call, where every error corresponds to a variant of `blurhash::Error`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlurhashStatus {
+    Ok = 0,
+    /// A required pointer argument was NULL.
+    NullPointer = 1,
+    HashTooShort = 2,
+    LengthMismatch = 3,
+    InvalidAscii = 4,
+    InvalidBase83 = 5,
+    ComponentsOutOfRange = 6,
+    ValueOutOfRange = 7,
+    BufferSizeMismatch = 8,
+    StrideTooSmall = 9,
+    InvalidDimensions = 10,
+    InvalidBudget = 11,
+}
+
+impl From<Error> for BlurhashStatus {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::HashTooShort => BlurhashStatus::HashTooShort,
+            Error::LengthMismatch { .. } => BlurhashStatus::LengthMismatch,
+            Error::InvalidAscii { .. } => BlurhashStatus::InvalidAscii,
+            Error::InvalidBase83 { .. } => BlurhashStatus::InvalidBase83,
+            Error::ComponentsOutOfRange => BlurhashStatus::ComponentsOutOfRange,
+            Error::ValueOutOfRange { .. } => BlurhashStatus::ValueOutOfRange,
+            Error::BufferSizeMismatch { .. } => BlurhashStatus::BufferSizeMismatch,
+            Error::StrideTooSmall { .. } => BlurhashStatus::StrideTooSmall,
+            Error::InvalidDimensions { .. } => BlurhashStatus::InvalidDimensions,
+            Error::InvalidBudget { .. } => BlurhashStatus::InvalidBudget,
+        }
+    }
+}
+
+impl From<Result<(), Error>> for BlurhashStatus {
+    fn from(result: Result<(), Error>) -> Self {
+        match result {
+            Ok(()) => BlurhashStatus::Ok,
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// Calculates the blurhash for a tightly packed RGBA8 image of `width * height * 4` bytes.
+///
+/// The hash is written to `out` as a NUL-terminated string. `out_len` must leave room for
+/// `4 + 2 * components_x * components_y` characters and the NUL, which
+/// `BLURHASH_MAX_LEN + 1` always does.
+///
+/// # Safety
+///
+/// `rgba` must point to `rgba_len` readable bytes, and `out` to `out_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn blurhash_encode(
+    components_x: u32,
+    components_y: u32,
+    width: u32,
+    height: u32,
+    rgba: *const u8,
+    rgba_len: usize,
+    out: *mut c_char,
+    out_len: usize,
+) -> BlurhashStatus {
+    if rgba.is_null() || out.is_null() {
+        return BlurhashStatus::NullPointer;
+    }
+    let rgba = core::slice::from_raw_parts(rgba, rgba_len);
+    let out = core::slice::from_raw_parts_mut(out as *mut u8, out_len);
+
+    let blurhash = match crate::encode(components_x, components_y, width, height, rgba) {
+        Ok(blurhash) => blurhash,
+        Err(e) => return e.into(),
+    };
+    if out.len() <= blurhash.len() {
+        return BlurhashStatus::BufferSizeMismatch;
+    }
+    out[..blurhash.len()].copy_from_slice(blurhash.as_bytes());
+    out[blurhash.len()] = 0;
+    BlurhashStatus::Ok
+}
+
+/// Decodes a NUL-terminated blurhash to a `width` by `height` RGBA8 image, into a buffer of
+/// exactly `width * height * 4` bytes.
+///
+/// The punch parameter can be used to de- or increase the contrast of the
+/// resulting image. Decoding does not allocate.
+///
+/// # Safety
+///
+/// `blurhash` must be a NUL-terminated string, and `pixels` must point to `pixels_len` writable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn blurhash_decode_into(
+    blurhash: *const c_char,
+    width: u32,
+    height: u32,
+    punch: f32,
+    pixels: *mut u8,
+    pixels_len: usize,
+) -> BlurhashStatus {
+    if pixels.is_null() {
+        return BlurhashStatus::NullPointer;
+    }
+    let blurhash: Blurhash = match to_str(blurhash).map(str::parse) {
+        Ok(Ok(blurhash)) => blurhash,
+        Ok(Err(e)) => return e.into(),
+        Err(status) => return status,
+    };
+    let pixels = core::slice::from_raw_parts_mut(pixels, pixels_len);
+
+    blurhash.decode_into(pixels, width, height, punch).into()
+}
+
+/// Checks that a NUL-terminated blurhash is well-formed, without decoding it.
+///
+/// On success, the component counts are stored in `components_x` and `components_y`, unless they
+/// are NULL.
+///
+/// # Safety
+///
+/// `blurhash` must be a NUL-terminated string, and `components_x` and `components_y` must each be
+/// NULL or point to a writable `uint32_t`.
+#[no_mangle]
+pub unsafe extern "C" fn blurhash_validate(
+    blurhash: *const c_char,
+    components_x: *mut u32,
+    components_y: *mut u32,
+) -> BlurhashStatus {
+    let info = match to_str(blurhash) {
+        Ok(blurhash) => crate::validate(blurhash),
+        Err(status) => return status,
+    };
+
+    match info {
+        Ok(info) => {
+            if !components_x.is_null() {
+                *components_x = info.components_x;
+            }
+            if !components_y.is_null() {
+                *components_y = info.components_y;
+            }
+            BlurhashStatus::Ok
+        }
+        Err(e) => e.into(),
+    }
+}
+
+/// Borrows a NUL-terminated C string, which has to be UTF-8 to be a valid blurhash.
+unsafe fn to_str<'a>(blurhash: *const c_char) -> Result<&'a str, BlurhashStatus> {
+    if blurhash.is_null() {
+        return Err(BlurhashStatus::NullPointer);
+    }
+    core::str::from_utf8(CStr::from_ptr(blurhash).to_bytes())
+        .map_err(|_| BlurhashStatus::InvalidAscii)
+}
@@ -0,0 +1,468 @@
+use crate::util::{floor, sqrt};
+use crate::validate::{out_of_range, MAX_DC};
+use crate::{ac, base83, dc, decoder, view, Error, Field, PixelFormat};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+/// The largest number of AC components a blurhash can carry.
+pub(crate) const MAX_AC: usize = 9 * 9 - 1;
+
+/// A parsed blurhash.
+///
+/// Parsing validates the hash and decodes its base83 fields once, so the same hash can then be
+/// decoded many times, at different sizes, without re-parsing it. Formatting a `Blurhash` yields
+/// exactly the string it was parsed from.
+///
+/// ```
+/// use blurhash::Blurhash;
+///
+/// let blurhash: Blurhash = "LNAdAqj[00aymkj[TKay9}ay-Sj[".parse().unwrap();
+/// assert_eq!(blurhash.components(), (4, 3));
+///
+/// let small = blurhash.decode(8, 6, 1.0).unwrap();
+/// let large = blurhash.decode(64, 48, 1.0).unwrap();
+///
+/// assert_eq!(blurhash.to_string(), "LNAdAqj[00aymkj[TKay9}ay-Sj[");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Blurhash {
+    components_x: u32,
+    components_y: u32,
+    quantised_maximum_value: u32,
+    dc: u32,
+    ac: [u32; MAX_AC],
+}
+
+impl Blurhash {
+    /// Quantises the projected DCT factors of an image.
+    pub(crate) fn from_factors(components_x: u32, components_y: u32, factors: &[[f32; 3]]) -> Self {
+        let dc = factors[0];
+        let ac = &factors[1..];
+
+        let maximum_value: f32;
+        let quantised_maximum_value;
+        if !ac.is_empty() {
+            let actualmaximum_value = ac
+                .iter()
+                .flatten()
+                .map(|x| f32::abs(*x))
+                .reduce(f32::max)
+                .unwrap_or(0.0);
+
+            quantised_maximum_value = floor(actualmaximum_value * 166. - 0.5).clamp(0., 82.) as u32;
+            maximum_value = (quantised_maximum_value + 1) as f32 / 166.;
+        } else {
+            quantised_maximum_value = 0;
+            maximum_value = 1.;
+        }
+
+        let mut quantised_ac = [0; MAX_AC];
+        for (quantised, ac) in quantised_ac.iter_mut().zip(ac) {
+            *quantised = ac::encode(*ac, maximum_value);
+        }
+
+        Blurhash {
+            components_x,
+            components_y,
+            quantised_maximum_value,
+            dc: dc::encode(dc),
+            ac: quantised_ac,
+        }
+    }
+
+    /// The number of horizontal and vertical components.
+    pub fn components(&self) -> (u32, u32) {
+        (self.components_x, self.components_y)
+    }
+
+    /// The quantised maximum magnitude of the AC components, in the range 0-82.
+    pub fn quantised_maximum_value(&self) -> u32 {
+        self.quantised_maximum_value
+    }
+
+    /// The maximum magnitude of the AC components, in linear light.
+    pub fn maximum_value(&self) -> f32 {
+        (self.quantised_maximum_value + 1) as f32 / 166.
+    }
+
+    /// The DC component, which is the average color of the image, in linear light.
+    pub fn dc(&self) -> [f32; 3] {
+        dc::decode(self.dc)
+    }
+
+    /// The average color of the image as sRGB, without decoding it.
+    ///
+    /// Returns an error if the DC value does not fit in 24 bits, as [validate][crate::validate]
+    /// does, instead of truncating it.
+    ///
+    /// ```
+    /// use blurhash::Blurhash;
+    ///
+    /// let blurhash: Blurhash = "LNAdAqj[00aymkj[TKay9}ay-Sj[".parse().unwrap();
+    /// assert_eq!(blurhash.average_color().unwrap(), [91, 92, 95]);
+    /// ```
+    pub fn average_color(&self) -> Result<[u8; 3], Error> {
+        if self.dc > MAX_DC {
+            return Err(out_of_range(Field::Dc));
+        }
+        Ok([(self.dc >> 16) as u8, (self.dc >> 8) as u8, self.dc as u8])
+    }
+
+    /// The relative luminance of the average color, between 0.0 (black) and 1.0 (white), as
+    /// defined by WCAG.
+    pub fn relative_luminance(&self) -> f32 {
+        let [r, g, b] = self.dc();
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    }
+
+    /// Whether the placeholder is predominantly dark, in which case light text contrasts better
+    /// with it than dark text does.
+    pub fn is_dark(&self) -> bool {
+        // The luminance at which black and white text have the same WCAG contrast ratio.
+        const EQUAL_CONTRAST_LUMINANCE: f32 = 0.179;
+        self.relative_luminance() < EQUAL_CONTRAST_LUMINANCE
+    }
+
+    /// A perceptual distance to another blurhash, for finding near-duplicate images, which works
+    /// on the components directly instead of decoding them.
+    ///
+    /// This is the Euclidean distance between the components, in linear light, with the DC
+    /// component counting double, since it sets the overall color. If the hashes have different
+    /// component counts, only the low-frequency components that both have are compared, so the
+    /// same image hashed at 4x3 and at 9x9 is still close. Identical hashes have a distance of 0.
+    ///
+    /// ```
+    /// use blurhash::Blurhash;
+    ///
+    /// let a: Blurhash = "LNAdAqj[00aymkj[TKay9}ay-Sj[".parse().unwrap();
+    /// let b: Blurhash = "LGF5]+Yk^6#M@-5c,1J5@[or[Q6.".parse().unwrap();
+    ///
+    /// assert_eq!(a.distance(&a), 0.);
+    /// assert!(a.distance(&b) > 0.1);
+    /// ```
+    pub fn distance(&self, other: &Blurhash) -> f32 {
+        const DC_WEIGHT: f32 = 2.;
+
+        let squared_distance =
+            |a: [f32; 3], b: [f32; 3]| (0..3).map(|c| (a[c] - b[c]) * (a[c] - b[c])).sum::<f32>();
+        let mut sum = DC_WEIGHT * squared_distance(self.dc(), other.dc());
+
+        let components_x = self.components_x.min(other.components_x) as usize;
+        let components_y = self.components_y.min(other.components_y) as usize;
+        let ac = |blurhash: &Blurhash, i: usize, j: usize| {
+            let index = j * blurhash.components_x as usize + i - 1;
+            ac::decode(blurhash.ac[index], blurhash.maximum_value())
+        };
+        for j in 0..components_y {
+            for i in 0..components_x {
+                if i == 0 && j == 0 {
+                    continue;
+                }
+                sum += squared_distance(ac(self, i, j), ac(other, i, j));
+            }
+        }
+
+        sqrt(sum)
+    }
+
+    /// The AC components in row-major order, in linear light.
+    pub fn ac(&self) -> impl ExactSizeIterator<Item = [f32; 3]> + '_ {
+        let maximum_value = self.maximum_value();
+        self.quantised_ac()
+            .iter()
+            .map(move |&value| ac::decode(value, maximum_value))
+    }
+
+    pub(crate) fn quantised_dc(&self) -> u32 {
+        self.dc
+    }
+
+    pub(crate) fn quantised_ac(&self) -> &[u32] {
+        &self.ac[..(self.components_x * self.components_y) as usize - 1]
+    }
+
+    /// Decodes this blurhash to an RGBA8 image of the specified size.
+    ///
+    /// The punch parameter can be used to de- or increase the contrast of the
+    /// resulting image.
+    pub fn decode(&self, width: u32, height: u32, punch: f32) -> Result<Vec<u8>, Error> {
+        let mut pixels = vec![0; view::rgba_len(width, height)?];
+        self.decode_into(&mut pixels, width, height, punch)
+            .map(|()| pixels)
+    }
+
+    /// Decodes this blurhash to an RGBA8 image of the specified size into an existing buffer.
+    ///
+    /// Unlike [Decoder][crate::Decoder], this does not allocate, so it also works without a heap.
+    ///
+    /// The punch parameter can be used to de- or increase the contrast of the
+    /// resulting image.
+    pub fn decode_into(
+        &self,
+        pixels: &mut [u8],
+        width: u32,
+        height: u32,
+        punch: f32,
+    ) -> Result<(), Error> {
+        view::check_rgba_len(pixels, width, height)?;
+
+        let stride = width as usize * 4;
+        self.decode_into_layout(pixels, stride, PixelFormat::Rgba8, width, height, punch)
+    }
+
+    /// Decodes this blurhash into an existing buffer of pixels in the given format, whose rows
+    /// start `stride` bytes apart.
+    ///
+    /// See [decode_into_layout][crate::decode_into_layout].
+    pub fn decode_into_layout(
+        &self,
+        pixels: &mut [u8],
+        stride: usize,
+        format: PixelFormat,
+        width: u32,
+        height: u32,
+        punch: f32,
+    ) -> Result<(), Error> {
+        decoder::decode_into_layout(pixels, stride, format, self, width, height, punch)
+    }
+
+    /// Dequantises all components, with the AC components scaled by `punch`.
+    ///
+    /// Only the first `components_x * components_y` colors are set.
+    pub(crate) fn colors(&self, punch: f32) -> [[f32; 3]; MAX_AC + 1] {
+        let maximum_value = self.maximum_value() * punch;
+
+        let mut colors = [[0.; 3]; MAX_AC + 1];
+        colors[0] = dc::decode(self.dc);
+        for (color, &value) in colors[1..].iter_mut().zip(self.quantised_ac()) {
+            *color = ac::decode(value, maximum_value);
+        }
+        colors
+    }
+}
+
+impl FromStr for Blurhash {
+    type Err = Error;
+
+    fn from_str(blurhash: &str) -> Result<Self, Error> {
+        if let Some(offset) = blurhash.bytes().position(|byte| !byte.is_ascii()) {
+            return Err(Error::InvalidAscii { offset });
+        }
+
+        let (num_x, num_y) = components(blurhash)?;
+
+        let quantised_maximum_value = decode_field(blurhash, Field::MaximumValue)?;
+        let dc = decode_field(blurhash, Field::Dc)?;
+
+        let mut ac = [0; MAX_AC];
+        for (index, ac) in ac.iter_mut().enumerate().take(num_x * num_y - 1) {
+            *ac = decode_field(blurhash, Field::Ac(index))?;
+        }
+
+        Ok(Blurhash {
+            components_x: num_x as u32,
+            components_y: num_y as u32,
+            quantised_maximum_value,
+            dc,
+            ac,
+        })
+    }
+}
+
+impl fmt::Display for Blurhash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ac = self.quantised_ac();
+
+        let mut blurhash = String::with_capacity(
+            // 1 byte for size flag
+            1
+            // 1 byte for maximum value
+            + 1
+            // 4 bytes for DC
+            + 4
+            // 2 bytes for each AC
+            + 2 * ac.len(),
+        );
+
+        let size_flag = (self.components_x - 1) + (self.components_y - 1) * 9;
+        base83::encode_into(size_flag, 1, &mut blurhash);
+        base83::encode_into(self.quantised_maximum_value, 1, &mut blurhash);
+        base83::encode_into(self.dc, 4, &mut blurhash);
+        for &value in ac {
+            base83::encode_into(value, 2, &mut blurhash);
+        }
+
+        f.write_str(&blurhash)
+    }
+}
+
+fn components(blurhash: &str) -> Result<(usize, usize), Error> {
+    if blurhash.len() < 6 {
+        return Err(Error::HashTooShort);
+    }
+
+    let size_flag = decode_field(blurhash, Field::SizeFlag)?;
+    let num_y = (floor(size_flag as f32 / 9.) + 1.) as usize;
+    let num_x = ((size_flag % 9) + 1) as usize;
+
+    let expected = 4 + 2 * num_x * num_y;
+    if blurhash.len() != expected {
+        return Err(Error::LengthMismatch {
+            expected,
+            actual: blurhash.len(),
+        });
+    }
+
+    Ok((num_x, num_y))
+}
+
+/// Decodes a single field of an ASCII blurhash that is long enough to hold it.
+fn decode_field(blurhash: &str, field: Field) -> Result<u32, Error> {
+    let offset = field.offset();
+    base83::decode(&blurhash[offset..offset + field.len()])
+        .map(|value| value as u32)
+        .map_err(|position| Error::InvalidBase83 {
+            byte: blurhash.as_bytes()[offset + position],
+            offset: offset + position,
+            field,
+        })
+}
+
+/// A strategy for well-formed blurhashes with arbitrary values, of every size that the size flag
+/// can express, including the 10 rows of `}` and `~`.
+#[cfg(test)]
+pub(crate) fn arbitrary_blurhash() -> impl proptest::strategy::Strategy<Value = String> {
+    use proptest::prelude::*;
+
+    (0..83usize)
+        .prop_flat_map(|size_flag| {
+            let components = (size_flag % 9 + 1) * (size_flag / 9 + 1);
+            (Just(size_flag), base83::base83_string(3 + 2 * components))
+        })
+        .prop_map(|(size_flag, rest)| {
+            let mut blurhash = String::new();
+            base83::encode_into(size_flag as u32, 1, &mut blurhash);
+            blurhash + &rest
+        })
+}
+
+/// A strategy for the hashes of random images, along with arbitrary hashes, some of which have
+/// 10 rows.
+#[cfg(test)]
+pub(crate) fn blurhashes() -> impl proptest::strategy::Strategy<Value = String> {
+    use proptest::prelude::*;
+
+    prop_oneof![
+        (
+            1..=9u32,
+            1..=9u32,
+            proptest::collection::vec(any::<u8>(), 8 * 8 * 4)
+        )
+            .prop_map(|(components_x, components_y, image)| {
+                crate::encode(components_x, components_y, 8, 8, &image).unwrap()
+            }),
+        arbitrary_blurhash(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Blurhash;
+    use crate::{decode, Error, Field};
+    use proptest::prelude::*;
+
+    const OCTOCAT: &str = "LNAdAqj[00aymkj[TKay9}ay-Sj[";
+
+    #[test]
+    fn inspect() {
+        let blurhash: Blurhash = OCTOCAT.parse().unwrap();
+
+        assert_eq!(blurhash.components(), (4, 3));
+        assert_eq!(blurhash.quantised_maximum_value(), 23);
+        assert_eq!(blurhash.ac().len(), 11);
+        assert_eq!(blurhash.dc(), crate::dc::decode(0x5b5c5f));
+    }
+
+    #[test]
+    fn average_color() {
+        let blurhash: Blurhash = OCTOCAT.parse().unwrap();
+        assert_eq!(blurhash.average_color(), Ok([0x5b, 0x5c, 0x5f]));
+        assert!(blurhash.is_dark() == (blurhash.relative_luminance() < 0.179));
+
+        let white = crate::encode(1, 1, 1, 1, &[255; 4]).unwrap();
+        let white: Blurhash = white.parse().unwrap();
+        assert_eq!(white.average_color(), Ok([255, 255, 255]));
+        assert!((white.relative_luminance() - 1.).abs() < 1e-6);
+        assert!(!white.is_dark());
+
+        let black = crate::encode(1, 1, 1, 1, &[0, 0, 0, 255]).unwrap();
+        let black: Blurhash = black.parse().unwrap();
+        assert!(black.relative_luminance() < 0.001);
+
+        // "~~~~" is 83^4 - 1, which does not fit in 24 bits.
+        let out_of_range: Blurhash = "00~~~~".parse().unwrap();
+        assert_eq!(
+            out_of_range.average_color(),
+            Err(Error::ValueOutOfRange {
+                offset: 2,
+                field: Field::Dc
+            })
+        );
+        assert!(black.is_dark());
+    }
+
+    #[test]
+    fn decode_many_sizes() {
+        let blurhash: Blurhash = OCTOCAT.parse().unwrap();
+
+        for (width, height) in [(1, 1), (32, 32), (17, 63)] {
+            assert_eq!(
+                blurhash.decode(width, height, 1.).unwrap(),
+                decode(OCTOCAT, width, height, 1.).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(
+            "LNAdA".parse::<Blurhash>(),
+            Err(Error::HashTooShort)
+        ));
+        assert!(matches!(
+            "LNAdAqj[00aymkj[TKay9}ay-Sj".parse::<Blurhash>(),
+            Err(Error::LengthMismatch { .. })
+        ));
+        assert_eq!(
+            "LNAdAqj[00aymkj[TKay9}ay-Sj\"".parse::<Blurhash>(),
+            Err(Error::InvalidBase83 {
+                byte: b'"',
+                offset: 27,
+                field: Field::Ac(10),
+            })
+        );
+        assert_eq!(
+            "LNA\"Aqj[00aymkj[TKay9}ay-Sj[".parse::<Blurhash>(),
+            Err(Error::InvalidBase83 {
+                byte: b'"',
+                offset: 3,
+                field: Field::Dc,
+            })
+        );
+        assert_eq!(
+            "LNAdAqj[0ͱymkj[TKay9}ay-Sj[".parse::<Blurhash>(),
+            Err(Error::InvalidAscii { offset: 9 })
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn roundtrip(blurhash in super::arbitrary_blurhash()) {
+            let parsed: Blurhash = blurhash.parse().unwrap();
+            prop_assert_eq!(parsed.to_string(), blurhash);
+        }
+    }
+}
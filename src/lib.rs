@@ -24,27 +24,78 @@
 //! Blurhash-rs supports directly decoding into an `ImageBuffer` of the `image` crate. Add the `image` feature flag, then do:
 //!
 //! ```no_run
+//! # #[cfg(feature = "image")] {
 //! use blurhash::decode_image;
 //!
 //! let blurhash = "LBAdAqof00WCqZj[PDay0.WB}pof";
 //! let width = 50;
 //! let height = 50;
 //! let image_buffer = decode_image(blurhash, width, height, 1.0).unwrap();
+//! # }
 //! ```
 //!
+//! ### `no_std`
+//!
+//! Blurhash-rs only needs `alloc`. Disable the default `std` feature and enable `libm`, which
+//! provides the floating point functions that `core` lacks:
+//!
+//! ```toml
+//! blurhash = { version = "0.2", default-features = false, features = ["libm", "fast-linear-to-srgb"] }
+//! ```
+//!
+//! [Blurhash::decode_into] and [Blurhash::decode_into_layout] do not allocate, so placeholders can
+//! be rendered straight into a framebuffer on targets without a heap.
+//!
 //! [1]: https://github.com/woltapp/blurhash
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!("blurhash needs either the `std` or the `libm` feature");
+
+extern crate alloc;
+
 mod ac;
+mod adaptive;
 mod base83;
 mod dc;
+mod decoder;
+mod downsample;
 mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "fixed-point")]
+pub mod fixed;
+mod hash;
+#[cfg(feature = "std")]
+pub mod metrics;
+mod options;
+pub mod pixel;
 mod util;
-
-pub use error::Error;
-
-use std::f32::consts::PI;
-use util::{linear_to_srgb, srgb_to_linear};
+mod validate;
+mod view;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use adaptive::{encode_adaptive, AdaptiveEncoding, Attempt};
+pub use decoder::Decoder;
+pub use error::{Error, Field};
+pub use hash::Blurhash;
+pub use options::{AlphaMode, EncodeOptions};
+pub use pixel::PixelFormat;
+pub use validate::{validate, ValidatedInfo};
+pub use view::ImageView;
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::f32::consts::PI;
+use downsample::Downsampled;
+use util::{cos, ln};
+use view::{check_rgba_len, rgba_len};
 
 /// Calculates the blurhash for an image using the given x and y component counts.
+///
+/// The image must be a tightly packed RGBA8 buffer of exactly `width * height * 4` bytes.
 pub fn encode(
     components_x: u32,
     components_y: u32,
@@ -52,217 +103,382 @@ pub fn encode(
     height: u32,
     rgba_image: &[u8],
 ) -> Result<String, Error> {
-    if !(1..=9).contains(&components_x) || !(1..=9).contains(&components_y) {
-        return Err(Error::ComponentsOutOfRange);
-    }
-
-    let mut factors: Vec<[f32; 3]> =
-        Vec::with_capacity(components_x as usize * components_y as usize);
+    check_rgba_len(rgba_image, width, height)?;
 
-    for y in 0..components_y {
-        for x in 0..components_x {
-            let factor = multiply_basis_function(x, y, width, height, rgba_image);
-            factors.push(factor);
-        }
-    }
-
-    let dc = factors[0];
-    let ac = &factors[1..];
-
-    let mut blurhash = String::with_capacity(
-        // 1 byte for size flag
-        1
-        // 1 byte for maximum value
-        + 1
-        // 4 bytes for DC
-        + 4
-        // 2 bytes for each AC
-        + 2 * ac.len(),
-    );
-
-    let size_flag = (components_x - 1) + (components_y - 1) * 9;
-    base83::encode_into(size_flag, 1, &mut blurhash);
-
-    let maximum_value: f32;
-    if !ac.is_empty() {
-        let actualmaximum_value = ac
-            .iter()
-            .flatten()
-            .map(|x| f32::abs(*x))
-            .reduce(f32::max)
-            .unwrap_or(0.0);
+    encode_view(
+        components_x,
+        components_y,
+        &ImageView::new(rgba_image, width, height),
+    )
+}
 
-        let quantised_maximum_value =
-            f32::floor(actualmaximum_value * 166. - 0.5).clamp(0., 82.) as u32;
+/// Calculates the blurhash for an image, choosing the component counts with [auto_components].
+///
+/// The image must be a tightly packed RGBA8 buffer of exactly `width * height * 4` bytes. Returns
+/// the blurhash, which is at most `4 + 2 * budget` characters long, and the chosen
+/// `(components_x, components_y)`.
+///
+/// ```
+/// // A portrait photo gets more vertical than horizontal components.
+/// let pixels = vec![128; 300 * 400 * 4];
+/// let (blurhash, components) = blurhash::encode_auto(300, 400, &pixels, 12).unwrap();
+///
+/// assert_eq!(components, (3, 4));
+/// assert_eq!(blurhash.len(), 4 + 2 * 12);
+/// ```
+pub fn encode_auto(
+    width: u32,
+    height: u32,
+    rgba_image: &[u8],
+    budget: u32,
+) -> Result<(String, (u32, u32)), Error> {
+    let (components_x, components_y) = auto_components(width, height, budget)?;
+    let blurhash = encode(components_x, components_y, width, height, rgba_image)?;
+    Ok((blurhash, (components_x, components_y)))
+}
 
-        maximum_value = (quantised_maximum_value + 1) as f32 / 166.;
-        base83::encode_into(quantised_maximum_value, 1, &mut blurhash);
-    } else {
-        maximum_value = 1.;
-        base83::encode_into(0, 1, &mut blurhash);
+/// Chooses component counts that follow the aspect ratio of a `width` by `height` image, using at
+/// most `budget` components in total.
+///
+/// The budget sets both the level of detail and the length of the hash, which is
+/// `4 + 2 * components_x * components_y` characters. Budgets above 81, the most that 9 by 9
+/// components allow, are treated as 81.
+pub fn auto_components(width: u32, height: u32, budget: u32) -> Result<(u32, u32), Error> {
+    if width == 0 || height == 0 {
+        return Err(Error::InvalidDimensions { width, height });
     }
-
-    base83::encode_into(dc::encode(dc), 4, &mut blurhash);
-
-    for i in 0..components_y * components_x - 1 {
-        base83::encode_into(ac::encode(ac[i as usize], maximum_value), 2, &mut blurhash);
+    if budget == 0 {
+        return Err(Error::InvalidBudget { budget });
     }
 
-    Ok(blurhash)
-}
-
-fn multiply_basis_function(
-    component_x: u32,
-    component_y: u32,
-    width: u32,
-    height: u32,
-    rgb: &[u8],
-) -> [f32; 3] {
-    let mut r = 0.;
-    let mut g = 0.;
-    let mut b = 0.;
-    let normalisation = match (component_x, component_y) {
-        (0, 0) => 1.,
-        _ => 2.,
+    // Balance how far the components are from the aspect ratio against how much of the budget
+    // they leave unused, in log space, so that a portrait and a landscape image are mirrored.
+    // Matching the aspect ratio counts double, so that a square image gets 3x3 rather than 4x3
+    // components out of a budget of 12. Ties go to the longer edge, or to the width for a square
+    // image.
+    let aspect = ln(width as f32) - ln(height as f32);
+    let budget = budget.min(9 * 9);
+    let cost = |(x, y): (u32, u32)| {
+        let aspect_error = ln(x as f32) - ln(y as f32) - aspect;
+        let budget_error = ln((x * y) as f32 / budget as f32);
+        2. * aspect_error * aspect_error + budget_error * budget_error
     };
 
-    let bytes_per_row = width * 4;
+    let components = (1..=9u32)
+        .flat_map(|x| (1..=9u32).map(move |y| (x, y)))
+        .filter(|&(x, y)| x * y <= budget)
+        .min_by(|&a, &b| {
+            let longer_edge = |(x, y): (u32, u32)| if width >= height { x } else { y };
+            cost(a)
+                .total_cmp(&cost(b))
+                .then(longer_edge(b).cmp(&longer_edge(a)))
+        })
+        .unwrap_or((1, 1));
+    Ok(components)
+}
 
-    let pi_cx_over_width = PI * component_x as f32 / width as f32;
-    let pi_cy_over_height = PI * component_y as f32 / height as f32;
+/// Calculates the blurhash for a padded, cropped or non-RGBA [ImageView] using the given x and y component counts.
+///
+/// ```
+/// use blurhash::{encode_view, ImageView};
+///
+/// // A 4x2 frame whose rows are padded to 20 bytes, of which we only hash the right half.
+/// let frame = [255u8; 20 * 2];
+/// let view = ImageView::with_stride(&frame, 4, 2, 20).crop(2, 0, 2, 2).unwrap();
+/// let blurhash = encode_view(4, 3, &view).unwrap();
+/// ```
+pub fn encode_view(
+    components_x: u32,
+    components_y: u32,
+    image: &ImageView,
+) -> Result<String, Error> {
+    encode_with_options(components_x, components_y, image, &EncodeOptions::default())
+}
 
-    let mut cos_pi_cx_over_width = vec![0.; width as usize];
-    for x in 0..width {
-        cos_pi_cx_over_width[x as usize] = f32::cos(pi_cx_over_width * x as f32);
+/// Calculates the blurhash for an [ImageView] using the given x and y component counts and [EncodeOptions].
+///
+/// ```
+/// use blurhash::{encode, encode_with_options, AlphaMode, EncodeOptions, ImageView};
+///
+/// // A fully transparent image hashes as the background it is composited over.
+/// let transparent = [0u8; 4 * 4 * 4];
+/// let options = EncodeOptions::new().alpha(AlphaMode::Composite([255, 255, 255]));
+/// let blurhash =
+///     encode_with_options(4, 3, &ImageView::new(&transparent, 4, 4), &options).unwrap();
+///
+/// assert_eq!(blurhash, encode(4, 3, 4, 4, &[255; 4 * 4 * 4]).unwrap());
+/// ```
+pub fn encode_with_options(
+    components_x: u32,
+    components_y: u32,
+    image: &ImageView,
+    options: &EncodeOptions,
+) -> Result<String, Error> {
+    if !(1..=9).contains(&components_x) || !(1..=9).contains(&components_y) {
+        return Err(Error::ComponentsOutOfRange);
     }
 
-    let mut cos_pi_cy_over_height = vec![0.; height as usize];
-    for y in 0..height {
-        cos_pi_cy_over_height[y as usize] = f32::cos(pi_cy_over_height * y as f32);
+    if image.width() == 0 || image.height() == 0 {
+        return Err(Error::InvalidDimensions {
+            width: image.width(),
+            height: image.height(),
+        });
     }
-
-    for y in 0..height {
-        for x in 0..width {
-            let basis = cos_pi_cx_over_width[x as usize] * cos_pi_cy_over_height[y as usize];
-            r += basis * srgb_to_linear(rgb[(4 * x + y * bytes_per_row) as usize]);
-            g += basis * srgb_to_linear(rgb[(4 * x + 1 + y * bytes_per_row) as usize]);
-            b += basis * srgb_to_linear(rgb[(4 * x + 2 + y * bytes_per_row) as usize]);
-        }
+    if image.stride() < image.row_len() {
+        return Err(Error::StrideTooSmall {
+            stride: image.stride(),
+            minimum: image.row_len(),
+        });
+    }
+    if image.data().len() < image.required_len() {
+        return Err(Error::BufferSizeMismatch {
+            expected: image.required_len(),
+            actual: image.data().len(),
+        });
     }
 
-    let scale = normalisation / (width * height) as f32;
+    let factors = multiply_basis_functions(components_x, components_y, image, options);
 
-    [r * scale, g * scale, b * scale]
+    Ok(Blurhash::from_factors(components_x, components_y, &factors).to_string())
 }
 
-/// Decodes the given blurhash to an image of the specified size into an existing buffer.
+/// Projects the image onto all `components_x * components_y` basis functions in a single pass.
 ///
-/// The punch parameter can be used to de- or increase the contrast of the
-/// resulting image.
-pub fn decode_into(
-    pixels: &mut [u8],
-    blurhash: &str,
-    width: u32,
-    height: u32,
-    punch: f32,
-) -> Result<(), Error> {
-    if !blurhash.is_ascii() {
-        return Err(Error::InvalidAscii);
+/// Each pixel is linearized only once, and then added to every component in turn. Every component
+/// is summed in raster order, like projecting onto one basis function at a time does, so that the
+/// result does not change. With the `rayon` feature, the image is split into bands of 64 rows
+/// instead, which are projected in parallel and added up in band order. The bands do not depend on
+/// the number of threads, so neither does the hash, but adding them up rounds differently than a
+/// single pass does.
+fn multiply_basis_functions(
+    components_x: u32,
+    components_y: u32,
+    image: &ImageView,
+    options: &EncodeOptions,
+) -> Vec<[f32; 3]> {
+    let num_x = components_x as usize;
+    let num_y = components_y as usize;
+
+    let width = image.width();
+    let height = image.height();
+
+    match options.downsample {
+        Some(size) if width.max(height) > size => {
+            // Every cell stands in for the pixels it covers, so its cosines are evaluated at its
+            // center in the original image.
+            let cells = Downsampled::new(image, options.alpha, size);
+            let cos_i_pi_x_over_width = cosines(num_x, width, cells.centers_x());
+            let cos_j_pi_y_over_height = cosines(num_y, height, cells.centers_y());
+
+            project(
+                &cos_i_pi_x_over_width,
+                &cos_j_pi_y_over_height,
+                num_x,
+                num_y,
+                |y, row| cells.row(y, row),
+            )
+        }
+        _ => {
+            let xs: Vec<f32> = (0..width).map(|x| x as f32).collect();
+            let ys: Vec<f32> = (0..height).map(|y| y as f32).collect();
+            let cos_i_pi_x_over_width = cosines(num_x, width, &xs);
+            let cos_j_pi_y_over_height = cosines(num_y, height, &ys);
+
+            project(
+                &cos_i_pi_x_over_width,
+                &cos_j_pi_y_over_height,
+                num_x,
+                num_y,
+                |y, row| {
+                    image.format().linearize_row(image.row(y), row);
+                    options.alpha.apply(row) as f64
+                },
+            )
+        }
     }
+}
 
-    let (num_x, num_y) = components(blurhash)?;
-
-    assert_eq!(
-        (width * height * 4) as usize,
-        pixels.len(),
-        "buffer length equals 4 * width * height"
-    );
-
-    let quantised_maximum_value = base83::decode(&blurhash[1..2])?;
-    let maximum_value = (quantised_maximum_value + 1) as f32 / 166.;
-
-    let mut colors = vec![[0.; 3]; num_x * num_y];
-
-    for i in 0..colors.len() {
-        if i == 0 {
-            let value = base83::decode(&blurhash[2..6])?;
-            colors[i] = dc::decode(value as u32);
-        } else {
-            let value = base83::decode(&blurhash[4 + i * 2..6 + i * 2])?;
-            colors[i] = ac::decode(value as u32, maximum_value * punch);
+/// Precomputes the cosines of `components` basis functions over an edge of `len` pixels, at the
+/// given positions along it.
+fn cosines(components: usize, len: u32, positions: &[f32]) -> Vec<f32> {
+    let mut cosines = vec![0.; positions.len() * components];
+    for i in 0..components {
+        let pi_i_over_len = PI * i as f32 / len as f32;
+        for (cosine, position) in cosines[i..].iter_mut().step_by(components).zip(positions) {
+            *cosine = cos(pi_i_over_len * position);
         }
     }
+    cosines
+}
 
-    let colors: Vec<_> = colors.chunks(num_x).collect();
-
-    let bytes_per_row = width as usize * 4;
-
-    let pi_over_height = PI / height as f32;
-    let pi_over_width = PI / width as f32;
-
-    // Precompute the cosines
-    let mut cos_i_pi_x_over_width = vec![0.; width as usize * num_x];
-    let mut cos_j_pi_y_over_height = vec![0.; height as usize * num_y];
+/// Projects the rows returned by `linear_row` onto the basis functions, and normalises the result.
+///
+/// `linear_row(y, row)` fills `row` with linear colors that alpha has already been applied to, and
+/// returns its total weight.
+fn project<F>(
+    cos_i_pi_x_over_width: &[f32],
+    cos_j_pi_y_over_height: &[f32],
+    num_x: usize,
+    num_y: usize,
+    linear_row: F,
+) -> Vec<[f32; 3]>
+where
+    F: Fn(usize, &mut [[f32; 4]]) -> f64 + Sync,
+{
+    let height = cos_j_pi_y_over_height.len() / num_y;
+    let (mut factors, weight) = project_in_parallel(height, |rows| {
+        project_rows(
+            rows,
+            cos_i_pi_x_over_width,
+            cos_j_pi_y_over_height,
+            num_x,
+            num_y,
+            &linear_row,
+        )
+    });
 
-    for x in 0..width {
-        let pi_x_over_width = x as f32 * pi_over_width;
-        for i in 0..num_x {
-            cos_i_pi_x_over_width[x as usize * num_x + i] = f32::cos(pi_x_over_width * i as f32);
-        }
+    if weight == 0. {
+        // Nothing is visible, so there is nothing to normalise.
+        return factors;
     }
 
-    for y in 0..height {
-        let pi_y_over_height = y as f32 * pi_over_height;
-        for j in 0..num_y {
-            cos_j_pi_y_over_height[y as usize * num_y + j] = f32::cos(j as f32 * pi_y_over_height);
-        }
+    let weight = weight as f32;
+    for (i, factor) in factors.iter_mut().enumerate() {
+        let normalisation = if i == 0 { 1. } else { 2. };
+        let scale = normalisation / weight;
+        factor[0] *= scale;
+        factor[1] *= scale;
+        factor[2] *= scale;
     }
 
-    // Hint to the optimizer that the length of the slices is correct
-    assert!(height as usize * num_y == cos_j_pi_y_over_height.len());
-    assert!(width as usize * num_x == cos_i_pi_x_over_width.len());
-
-    for y in 0..height as usize {
-        let pixels = &mut pixels[y * bytes_per_row..][..bytes_per_row];
+    factors
+}
 
-        // More optimizer hints.
-        assert!(y * num_y + num_y <= cos_j_pi_y_over_height.len());
+/// The number of image rows that are projected together with the `rayon` feature.
+#[cfg(feature = "rayon")]
+const BAND_HEIGHT: usize = 64;
+
+/// Projects every band of [BAND_HEIGHT] rows on its own, on all cores, and adds them up in order.
+#[cfg(feature = "rayon")]
+fn project_in_parallel(
+    height: usize,
+    project: impl Fn(core::ops::Range<usize>) -> (Vec<[f32; 3]>, f64) + Sync,
+) -> (Vec<[f32; 3]>, f64) {
+    use rayon::prelude::*;
+    let bands: Vec<_> = (0..height)
+        .step_by(BAND_HEIGHT)
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|y| project(y..height.min(y + BAND_HEIGHT)))
+        .collect();
+
+    let mut bands = bands.into_iter();
+    let (mut factors, mut weight) = bands.next().unwrap_or_else(|| project(0..0));
+    for (band_factors, band_weight) in bands {
+        for (factor, band_factor) in factors.iter_mut().zip(band_factors) {
+            factor[0] += band_factor[0];
+            factor[1] += band_factor[1];
+            factor[2] += band_factor[2];
+        }
+        weight += band_weight;
+    }
+    (factors, weight)
+}
 
-        for x in 0..width as usize {
-            let mut pixel = [0.; 3];
+/// Without the `rayon` feature, all rows are projected in a single pass.
+#[cfg(not(feature = "rayon"))]
+fn project_in_parallel(
+    height: usize,
+    project: impl Fn(core::ops::Range<usize>) -> (Vec<[f32; 3]>, f64),
+) -> (Vec<[f32; 3]>, f64) {
+    project(0..height)
+}
 
-            let cos_j_pi_y_over_height = &cos_j_pi_y_over_height[y * num_y..][..num_y];
+/// Projects the given rows returned by `linear_row` onto all basis functions, returning their
+/// unnormalised factors and total weight.
+fn project_rows(
+    rows: core::ops::Range<usize>,
+    cos_i_pi_x_over_width: &[f32],
+    cos_j_pi_y_over_height: &[f32],
+    num_x: usize,
+    num_y: usize,
+    linear_row: &impl Fn(usize, &mut [[f32; 4]]) -> f64,
+) -> (Vec<[f32; 3]>, f64) {
+    let mut factors = vec![[0.; 3]; num_x * num_y];
+    let mut row = vec![[0.; 4]; cos_i_pi_x_over_width.len() / num_x];
+    let mut weight = 0f64;
+
+    for y in rows {
+        weight += linear_row(y, &mut row);
+        let cos_j_pi_y_over_height = &cos_j_pi_y_over_height[y * num_y..][..num_y];
+
+        for (x, &[r, g, b, _]) in row.iter().enumerate() {
             let cos_i_pi_x_over_width = &cos_i_pi_x_over_width[x * num_x..][..num_x];
-
-            assert_eq!(cos_j_pi_y_over_height.len(), colors.len());
-            assert_eq!(cos_j_pi_y_over_height.len(), num_y);
-
-            for (cos_j, colors) in cos_j_pi_y_over_height.iter().zip(colors.iter()) {
-                assert_eq!(cos_i_pi_x_over_width.len(), colors.len());
-                assert_eq!(cos_i_pi_x_over_width.len(), num_x);
-
-                for (cos_i, color) in cos_i_pi_x_over_width.iter().zip(colors.iter()) {
+            for (factors, cos_j) in factors.chunks_exact_mut(num_x).zip(cos_j_pi_y_over_height) {
+                for (factor, cos_i) in factors.iter_mut().zip(cos_i_pi_x_over_width) {
                     let basis = cos_i * cos_j;
-
-                    pixel[0] += color[0] * basis;
-                    pixel[1] += color[1] * basis;
-                    pixel[2] += color[2] * basis;
+                    factor[0] += basis * r;
+                    factor[1] += basis * g;
+                    factor[2] += basis * b;
                 }
             }
+        }
+    }
 
-            let int_r = linear_to_srgb(pixel[0]);
-            let int_g = linear_to_srgb(pixel[1]);
-            let int_b = linear_to_srgb(pixel[2]);
+    (factors, weight)
+}
 
-            let pixels = &mut pixels[4 * x..][..4];
+/// Decodes the given blurhash to an image of the specified size into an existing buffer.
+///
+/// The punch parameter can be used to de- or increase the contrast of the
+/// resulting image.
+pub fn decode_into(
+    pixels: &mut [u8],
+    blurhash: &str,
+    width: u32,
+    height: u32,
+    punch: f32,
+) -> Result<(), Error> {
+    blurhash
+        .parse::<Blurhash>()?
+        .decode_into(pixels, width, height, punch)
+}
 
-            pixels[0] = int_r;
-            pixels[1] = int_g;
-            pixels[2] = int_b;
-            pixels[3] = 255u8;
-        }
-    }
-    Ok(())
+/// Decodes the given blurhash into an existing buffer of pixels in the given format, whose rows
+/// start `stride` bytes apart.
+///
+/// Only the first `width * format.bytes_per_pixel()` bytes of every row are written, so any row
+/// padding is left untouched. The punch parameter can be used to de- or increase the contrast of
+/// the resulting image.
+///
+/// ```
+/// use blurhash::{decode_into_layout, PixelFormat};
+///
+/// // A 32x32 RGB565 framebuffer region with rows of 128 bytes.
+/// let mut framebuffer = vec![0u8; 128 * 32];
+/// decode_into_layout(
+///     &mut framebuffer,
+///     128,
+///     PixelFormat::Rgb565,
+///     "LBAdAqof00WCqZj[PDay0.WB}pof",
+///     32,
+///     32,
+///     1.0,
+/// )
+/// .unwrap();
+/// ```
+pub fn decode_into_layout(
+    pixels: &mut [u8],
+    stride: usize,
+    format: PixelFormat,
+    blurhash: &str,
+    width: u32,
+    height: u32,
+    punch: f32,
+) -> Result<(), Error> {
+    blurhash
+        .parse::<Blurhash>()?
+        .decode_into_layout(pixels, stride, format, width, height, punch)
 }
 
 /// Decodes the given blurhash to an image of the specified size.
@@ -270,29 +486,27 @@ pub fn decode_into(
 /// The punch parameter can be used to de- or increase the contrast of the
 /// resulting image.
 pub fn decode(blurhash: &str, width: u32, height: u32, punch: f32) -> Result<Vec<u8>, Error> {
-    let bytes_per_row = width * 4;
-    let mut pixels = vec![0; (bytes_per_row * height) as usize];
+    let mut pixels = vec![0; rgba_len(width, height)?];
     decode_into(&mut pixels, blurhash, width, height, punch).map(|()| pixels)
 }
 
-fn components(blurhash: &str) -> Result<(usize, usize), Error> {
-    if blurhash.len() < 6 {
-        return Err(Error::HashTooShort);
-    }
-
-    let size_flag = base83::decode(&blurhash[0..1])?;
-    let num_y = (f32::floor(size_flag as f32 / 9.) + 1.) as usize;
-    let num_x = ((size_flag % 9) + 1) as usize;
-
-    let expected = 4 + 2 * num_x * num_y;
-    if blurhash.len() != expected {
-        return Err(Error::LengthMismatch {
-            expected,
-            actual: blurhash.len(),
-        });
-    }
+/// Returns the average color of the image that the given blurhash represents, as sRGB.
+///
+/// The whole blurhash is checked like [validate] does, but none of it is decoded to pixels.
+pub fn average_color(blurhash: &str) -> Result<[u8; 3], Error> {
+    validate(blurhash)?;
+    blurhash.parse::<Blurhash>()?.average_color()
+}
 
-    Ok((num_x, num_y))
+/// A perceptual distance between two blurhashes, for finding near-duplicate images, computed
+/// without decoding them. See [Blurhash::distance].
+///
+/// ```
+/// let a = "LNAdAqj[00aymkj[TKay9}ay-Sj[";
+/// assert_eq!(blurhash::distance(a, a).unwrap(), 0.);
+/// ```
+pub fn distance(a: &str, b: &str) -> Result<f32, Error> {
+    Ok(a.parse::<Blurhash>()?.distance(&b.parse()?))
 }
 
 /// Calculates the blurhash for an [DynamicImage][image::DynamicImage] using the given x and y component counts.
@@ -322,12 +536,25 @@ pub fn encode_pixbuf(
     image: &gdk_pixbuf::Pixbuf,
 ) -> Result<String, Error> {
     use std::convert::TryInto;
-    encode(
+    let bytes = image.read_pixel_bytes();
+    // gdk-pixbuf only stores 8-bit RGB, with or without alpha.
+    let format = match image.n_channels() {
+        3 => PixelFormat::Rgb8,
+        _ => PixelFormat::Rgba8,
+    };
+    encode_view(
         components_x,
         components_y,
-        image.width().try_into().expect("non-negative width"),
-        image.height().try_into().expect("non-negative height"),
-        &image.read_pixel_bytes(),
+        &ImageView::with_layout(
+            &bytes,
+            image.width().try_into().expect("non-negative width"),
+            image.height().try_into().expect("non-negative height"),
+            image
+                .rowstride()
+                .try_into()
+                .expect("non-negative rowstride"),
+            format,
+        ),
     )
 }
 
@@ -380,6 +607,7 @@ pub fn decode_pixbuf(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::srgb_to_linear;
     use image::{EncodableLayout, GenericImageView};
     use proptest::prelude::*;
 
@@ -394,11 +622,47 @@ mod tests {
         assert_eq!(img[0..5], [1, 1, 1, 255, 1]);
     }
 
+    #[test]
+    fn average_color_matches_decode() {
+        // Without any AC contribution, decoding yields the average color, up to the precision of
+        // the linear to sRGB conversion.
+        let img = decode("LNAdAqj[00aymkj[TKay9}ay-Sj[", 1, 1, 0.).unwrap();
+        let color = average_color("LNAdAqj[00aymkj[TKay9}ay-Sj[").unwrap();
+        for (average, decoded) in color.iter().zip(&img) {
+            assert!((*average as i32 - *decoded as i32).abs() <= 1);
+        }
+        assert!(average_color("LNAdAqj[00aymkj[TKay9}ay-Sj").is_err());
+        assert!(matches!(
+            average_color("00~~~~"),
+            Err(Error::ValueOutOfRange {
+                field: Field::Dc,
+                ..
+            })
+        ));
+        // Size flag 81 would mean 10 vertical components.
+        let mut ten_rows = String::from("}");
+        ten_rows.push_str(&"0".repeat(3 + 2 * 10));
+        assert!(matches!(
+            average_color(&ten_rows),
+            Err(Error::ValueOutOfRange {
+                field: Field::SizeFlag,
+                ..
+            })
+        ));
+        assert!(matches!(
+            average_color("100000~~"),
+            Err(Error::ValueOutOfRange {
+                field: Field::Ac(0),
+                ..
+            })
+        ));
+    }
+
     #[test]
     fn decode_non_ascii() {
         assert!(matches!(
             decode("ͱZ", 50, 50, 1.0),
-            Err(Error::InvalidAscii)
+            Err(Error::InvalidAscii { offset: 0 })
         ));
     }
 
@@ -445,6 +709,44 @@ mod tests {
         assert_eq!(blurhash, "LNAdAqj[00aymkj[TKay9}ay-Sj[");
     }
 
+    #[test]
+    #[cfg(feature = "gdk-pixbuf")]
+    fn encode_rgb_pixbuf() {
+        let img = image::open("data/octocat.png").unwrap();
+        let (width, height) = img.dimensions();
+        let rgb = img.to_rgb8();
+
+        // Pad the rows, so that reading them with the wrong pixel size would go astray.
+        let stride = width as usize * 3 + 4;
+        let mut padded = vec![0; stride * height as usize];
+        for (src, dst) in rgb
+            .chunks(width as usize * 3)
+            .zip(padded.chunks_mut(stride))
+        {
+            dst[..src.len()].copy_from_slice(src);
+        }
+        let pixbuf = gdk_pixbuf::Pixbuf::from_bytes(
+            &gdk_pixbuf::glib::Bytes::from_owned(padded),
+            gdk_pixbuf::Colorspace::Rgb,
+            false,
+            8,
+            width as i32,
+            height as i32,
+            stride as i32,
+        );
+        assert_eq!(pixbuf.n_channels(), 3);
+
+        let expected = encode(
+            4,
+            3,
+            width,
+            height,
+            &image::DynamicImage::ImageRgb8(rgb).to_rgba8(),
+        )
+        .unwrap();
+        assert_eq!(encode_pixbuf(4, 3, &pixbuf).unwrap(), expected);
+    }
+
     #[test]
     #[cfg(feature = "gdk-pixbuf")]
     fn decode_blurhash_pixbuf() {
@@ -480,7 +782,7 @@ mod tests {
         let mut aggregated_error: usize = 0;
         let mut peak_error = 0;
         for (r, t) in result.iter().zip(target) {
-            let error = (*r as isize - *t as isize).abs() as usize;
+            let error = (*r as isize - *t as isize).unsigned_abs();
             aggregated_error += error;
             peak_error = peak_error.max(error);
         }
@@ -501,21 +803,272 @@ mod tests {
         );
     }
 
-    fn base83_string(len: usize) -> impl Strategy<Value = String> {
-        let reg = format!("([A-Za-z0-9#$%*+,-.:;=?@\\[\\]^_{{|}}~]){{{len}}}");
-        proptest::string::string_regex(&reg).unwrap()
+    #[test]
+    fn auto_components_follow_aspect_ratio() {
+        assert_eq!(auto_components(400, 300, 12), Ok((4, 3)));
+        assert_eq!(auto_components(300, 400, 12), Ok((3, 4)));
+        assert_eq!(auto_components(500, 500, 12), Ok((3, 3)));
+        assert_eq!(auto_components(1920, 1080, 20), Ok((6, 3)));
+        assert_eq!(auto_components(10000, 10, 81), Ok((9, 1)));
+        assert_eq!(auto_components(400, 300, 1000), Ok((9, 7)));
+        assert_eq!(auto_components(400, 300, 1), Ok((1, 1)));
+
+        for (width, height) in [(1, 1), (4, 3), (16, 9), (3, 1), (100, 7)] {
+            for budget in 1..=81 {
+                let (x, y) = auto_components(width, height, budget).unwrap();
+                assert!(x * y <= budget, "{}x{} over a budget of {}", x, y, budget);
+                if width == height {
+                    assert!(x >= y, "{}x{} for a square image", x, y);
+                    continue;
+                }
+                assert_eq!(
+                    auto_components(height, width, budget),
+                    Ok((y, x)),
+                    "{}x{} with a budget of {}",
+                    width,
+                    height,
+                    budget
+                );
+            }
+        }
+
+        assert_eq!(
+            auto_components(0, 300, 12),
+            Err(Error::InvalidDimensions {
+                width: 0,
+                height: 300
+            })
+        );
+        assert_eq!(
+            auto_components(400, 300, 0),
+            Err(Error::InvalidBudget { budget: 0 })
+        );
+    }
+
+    #[test]
+    fn encode_auto_portrait() {
+        let img = image::open("data/octocat.png").unwrap();
+        let img = img.crop_imm(64, 0, 128, 256).to_rgba8();
+        let (width, height) = img.dimensions();
+
+        let (blurhash, (components_x, components_y)) =
+            encode_auto(width, height, &img, 12).unwrap();
+        assert!(components_x < components_y);
+        assert_eq!(
+            blurhash,
+            encode(components_x, components_y, width, height, &img).unwrap()
+        );
     }
 
-    prop_compose! {
-        fn valid_blurhash()
-             (num_x in 1..10u32, num_y in 1..10u32)
-             (blurhash in base83_string(3 + 2 * num_x as usize * num_y as usize), num_x in Just(num_x), num_y in Just(num_y))
-                             -> String {
-            let mut blurhash_with_size = String::with_capacity(4 + 2 * num_x as usize * num_y as usize);
-            let size_flag = (num_x - 1) + (num_y - 1) * 9;
-            base83::encode_into(size_flag, 1, &mut blurhash_with_size);
-            blurhash_with_size.push_str(&blurhash);
-            blurhash_with_size
+    #[test]
+    fn distance_ignores_resolution() {
+        use image::imageops::FilterType;
+
+        let hash = |img: &image::DynamicImage, components_x, components_y| {
+            let (width, height) = img.dimensions();
+            encode(components_x, components_y, width, height, &img.to_rgba8()).unwrap()
+        };
+        let octocat = image::open("data/octocat.png").unwrap();
+        let original = hash(&octocat, 4, 3);
+        let other = hash(&image::open("data/wikipedia_logo.png").unwrap(), 4, 3);
+        let unrelated = distance(&original, &other).unwrap();
+
+        for &(width, height) in &[(128, 128), (64, 64), (100, 60)] {
+            let resized = hash(
+                &octocat.resize_exact(width, height, FilterType::Triangle),
+                4,
+                3,
+            );
+            let near = distance(&original, &resized).unwrap();
+            assert!(
+                near < unrelated / 4.,
+                "{}x{}: {} vs {}",
+                width,
+                height,
+                near,
+                unrelated
+            );
+            assert_eq!(near, distance(&resized, &original).unwrap());
+        }
+
+        let detailed = hash(&octocat, 9, 9);
+        assert!(distance(&original, &detailed).unwrap() < unrelated / 4.);
+        assert_eq!(distance(&original, &original).unwrap(), 0.);
+    }
+
+    #[test]
+    fn encode_padded_rows() {
+        let img = image::open("data/octocat.png").unwrap();
+        let (width, height) = img.dimensions();
+        let img = img.to_rgba8();
+
+        let stride = width as usize * 4 + 12;
+        let mut padded = vec![0xa5; stride * height as usize];
+        for (src, dst) in img
+            .chunks(width as usize * 4)
+            .zip(padded.chunks_mut(stride))
+        {
+            dst[..src.len()].copy_from_slice(src);
+        }
+
+        let view = ImageView::with_stride(&padded, width, height, stride);
+        assert_eq!(
+            encode_view(4, 3, &view).unwrap(),
+            "LNAdAqj[00aymkj[TKay9}ay-Sj["
+        );
+    }
+
+    #[test]
+    fn encode_cropped_region() {
+        let img = image::open("data/octocat.png").unwrap();
+        let (width, height) = img.dimensions();
+        let cropped = img.crop_imm(10, 20, width / 2, height / 3).to_rgba8();
+        let img = img.to_rgba8();
+
+        let view = ImageView::new(&img, width, height)
+            .crop(10, 20, width / 2, height / 3)
+            .unwrap();
+        assert_eq!(
+            encode_view(4, 3, &view).unwrap(),
+            encode(4, 3, width / 2, height / 3, &cropped).unwrap()
+        );
+
+        let view = ImageView::new(&img, width, height);
+        assert!(view.crop(1, 0, width, height).is_none());
+        assert!(view.crop(0, u32::MAX, 1, 2).is_none());
+    }
+
+    #[test]
+    fn encode_rejects_bad_input() {
+        assert_eq!(
+            encode(4, 3, 4, 4, &[0; 4 * 4 * 4 - 1]),
+            Err(Error::BufferSizeMismatch {
+                expected: 64,
+                actual: 63
+            })
+        );
+        assert_eq!(
+            encode(4, 3, 0, 4, &[]),
+            Err(Error::InvalidDimensions {
+                width: 0,
+                height: 4
+            })
+        );
+        assert_eq!(
+            encode(4, 3, u32::MAX, u32::MAX, &[0; 4]),
+            Err(Error::BufferSizeMismatch {
+                expected: (u32::MAX as usize)
+                    .saturating_mul(u32::MAX as usize)
+                    .saturating_mul(4),
+                actual: 4
+            })
+        );
+        assert_eq!(
+            encode_view(4, 3, &ImageView::with_stride(&[0; 64], 4, 4, 15)),
+            Err(Error::StrideTooSmall {
+                stride: 15,
+                minimum: 16
+            })
+        );
+        assert_eq!(
+            encode_view(4, 3, &ImageView::with_stride(&[0; 64], 4, 4, usize::MAX)),
+            Err(Error::BufferSizeMismatch {
+                expected: usize::MAX,
+                actual: 64
+            })
+        );
+    }
+
+    /// The tallest image that `multiply_basis_functions` projects in a single pass, and so exactly
+    /// like the reference does. With the `rayon` feature, taller images are added up in bands.
+    #[cfg(feature = "rayon")]
+    const MAX_REFERENCE_HEIGHT: u32 = BAND_HEIGHT as u32;
+    #[cfg(not(feature = "rayon"))]
+    const MAX_REFERENCE_HEIGHT: u32 = 160;
+
+    /// The straightforward per-component projection that `multiply_basis_functions` replaces.
+    fn multiply_basis_function_reference(
+        component_x: u32,
+        component_y: u32,
+        width: u32,
+        height: u32,
+        rgb: &[u8],
+    ) -> [f32; 3] {
+        let mut pixel = [0.; 3];
+        let normalisation = match (component_x, component_y) {
+            (0, 0) => 1.,
+            _ => 2.,
+        };
+        let bytes_per_row = width * 4;
+        let pi_cx_over_width = PI * component_x as f32 / width as f32;
+        let pi_cy_over_height = PI * component_y as f32 / height as f32;
+
+        for y in 0..height {
+            for x in 0..width {
+                let basis =
+                    f32::cos(pi_cx_over_width * x as f32) * f32::cos(pi_cy_over_height * y as f32);
+                for (c, value) in pixel.iter_mut().enumerate() {
+                    *value += basis
+                        * srgb_to_linear(rgb[(4 * x + c as u32 + y * bytes_per_row) as usize]);
+                }
+            }
+        }
+
+        let scale = normalisation / (width * height) as f32;
+        pixel.map(|value| value * scale)
+    }
+
+    #[test]
+    fn single_pass_matches_per_component_reference() {
+        let img = image::open("data/octocat.png").unwrap();
+        let (width, height) = img.dimensions();
+        let img = img.to_rgba8();
+
+        // Random images cover the other component counts.
+        for (components_x, components_y) in [(1, 1), (4, 3), (9, 1), (1, 9), (9, 9)] {
+            let mut reference = Vec::new();
+            for y in 0..components_y {
+                for x in 0..components_x {
+                    reference.push(multiply_basis_function_reference(x, y, width, height, &img));
+                }
+            }
+
+            assert_eq!(
+                encode(components_x, components_y, width, height, &img).unwrap(),
+                Blurhash::from_factors(components_x, components_y, &reference).to_string(),
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn rayon_is_deterministic() {
+        let img = image::open("data/SIPI_Jelly_Beans.tiff").unwrap();
+        let img = img.resize_exact(100, 200, image::imageops::FilterType::Triangle);
+        let img = img.to_rgba8();
+        let view = ImageView::new(&img, img.width(), img.height());
+
+        let in_pool = |threads: usize, project: &(dyn Fn() -> Vec<[f32; 3]> + Sync)| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(project)
+        };
+
+        for (components_x, components_y) in [(1, 9), (4, 3), (9, 9)] {
+            for options in [
+                EncodeOptions::new(),
+                EncodeOptions::new().alpha(AlphaMode::Weighted),
+                EncodeOptions::new().downsample(64),
+            ] {
+                let project =
+                    || multiply_basis_functions(components_x, components_y, &view, &options);
+                let serial = in_pool(1, &project);
+                for threads in [2, 8] {
+                    assert_eq!(in_pool(threads, &project), serial, "{} threads", threads);
+                }
+            }
         }
     }
 
@@ -539,11 +1092,58 @@ mod tests {
             let _ = decode(&blurhash, width, height, punch);
         }
 
+        #[test]
+        fn encode_doesnt_panic(
+            (width, height, len) in (0..32u32, 0..32u32).prop_flat_map(|(width, height)| {
+                let exact = (width * height * 4) as usize;
+                (Just(width), Just(height), prop_oneof![
+                    Just(exact),
+                    exact.saturating_sub(4)..exact + 4,
+                    0..32 * 32 * 4 + 4usize,
+                ])
+            }),
+            stride_padding in 0..8usize,
+        ) {
+            let image = vec![0x80; len];
+            let result = encode(4, 3, width, height, &image);
+            prop_assert_eq!(
+                result.is_ok(),
+                width > 0 && height > 0 && len == (width * height * 4) as usize
+            );
+
+            let stride = (width as usize * 4 + stride_padding).saturating_sub(4);
+            let _ = encode_view(4, 3, &ImageView::with_stride(&image, width, height, stride));
+        }
+
+        #[test]
+        fn single_pass_matches_per_component_reference_on_random_images(
+            (width, height, img) in (1..24u32, 1..=MAX_REFERENCE_HEIGHT)
+                .prop_flat_map(|(width, height)| {
+                    let len = (width * height * 4) as usize;
+                    (Just(width), Just(height), proptest::collection::vec(any::<u8>(), len))
+                }),
+            components_x in 1..10u32,
+            components_y in 1..10u32,
+        ) {
+            let mut reference = Vec::new();
+            for y in 0..components_y {
+                for x in 0..components_x {
+                    reference.push(multiply_basis_function_reference(x, y, width, height, &img));
+                }
+            }
+
+            let view = ImageView::new(&img, width, height);
+            prop_assert_eq!(
+                multiply_basis_functions(components_x, components_y, &view, &EncodeOptions::default()),
+                reference
+            );
+        }
+
         #[test]
         fn decode_valid_blurhash(
             width in 10..100u32,
             height in 10..100u32,
-            blurhash in valid_blurhash(),
+            blurhash in hash::arbitrary_blurhash(),
         ) {
 
             let img = decode(&blurhash, width, height, 1.);
@@ -0,0 +1,381 @@
+//! Measuring how faithfully a blurhash represents the image it was made from.
+//!
+//! All metrics compare the color channels of two RGBA8 buffers of the same size, and ignore alpha.
+//!
+//! ```
+//! use blurhash::metrics;
+//! use image::GenericImageView;
+//!
+//! let img = image::open("data/octocat.png").unwrap();
+//! let (width, height) = img.dimensions();
+//! let img = img.to_rgba8();
+//!
+//! let metrics = metrics::measure("LNAdAqj[00aymkj[TKay9}ay-Sj[", width, height, &img, None).unwrap();
+//! println!("PSNR {:.2} dB, SSIM {:.4}, ΔE {:.2}", metrics.psnr, metrics.ssim, metrics.delta_e);
+//! ```
+use crate::downsample::Downsampled;
+use crate::util::{linear_to_srgb_f32, srgb_to_linear};
+use crate::view::check_rgba_len;
+use crate::{AlphaMode, Blurhash, Error, ImageView};
+
+/// How closely two images match.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Metrics {
+    /// The peak signal-to-noise ratio in dB, which is infinite for identical images.
+    pub psnr: f32,
+    /// The mean structural similarity of the luma, between -1 and 1, where 1 means identical.
+    pub ssim: f32,
+    /// The mean CIEDE2000 color difference, where about 2.3 is just noticeable.
+    pub delta_e: f32,
+}
+
+/// Decodes a blurhash and compares it with the tightly packed RGBA8 image it was made from.
+///
+/// With `size`, the image is first box-filtered, in linear light, until neither edge is longer
+/// than `size` pixels, and the blurhash is decoded at that size instead. This is much faster for
+/// large images, and leaves out detail that no blurhash could represent anyway.
+pub fn measure(
+    blurhash: &str,
+    width: u32,
+    height: u32,
+    rgba_image: &[u8],
+    size: Option<u32>,
+) -> Result<Metrics, Error> {
+    check_rgba_len(rgba_image, width, height)?;
+    if width == 0 || height == 0 {
+        return Err(Error::InvalidDimensions { width, height });
+    }
+    let blurhash: Blurhash = blurhash.parse()?;
+
+    match size {
+        Some(size) if width.max(height) > size => {
+            let image = ImageView::new(rgba_image, width, height);
+            let cells = Downsampled::new(&image, AlphaMode::Ignore, size);
+            let reduced: Vec<u8> = cells
+                .cells()
+                .iter()
+                .flat_map(|&[r, g, b, weight]| {
+                    let srgb = |c: f32| (linear_to_srgb_f32(c / weight) * 255.).round() as u8;
+                    [srgb(r), srgb(g), srgb(b), 255]
+                })
+                .collect();
+            let (width, height) = (cells.width() as u32, cells.height() as u32);
+
+            compare(
+                &blurhash.decode(width, height, 1.)?,
+                &reduced,
+                width,
+                height,
+            )
+        }
+        _ => compare(
+            &blurhash.decode(width, height, 1.)?,
+            rgba_image,
+            width,
+            height,
+        ),
+    }
+}
+
+/// Compares two RGBA8 images of `width` by `height` pixels.
+///
+/// Empty images have no meaningful similarity, so zero dimensions are rejected.
+pub fn compare(a: &[u8], b: &[u8], width: u32, height: u32) -> Result<Metrics, Error> {
+    check_rgba_len(a, width, height)?;
+    check_rgba_len(b, width, height)?;
+    if width == 0 || height == 0 {
+        return Err(Error::InvalidDimensions { width, height });
+    }
+
+    Ok(Metrics {
+        psnr: psnr(a, b),
+        ssim: ssim(a, b, width, height),
+        delta_e: delta_e(a, b),
+    })
+}
+
+/// The peak signal-to-noise ratio between two RGBA8 buffers, in dB.
+fn psnr(a: &[u8], b: &[u8]) -> f32 {
+    let (mut squared_error, mut count) = (0f64, 0usize);
+    for (a, b) in a.chunks_exact(4).zip(b.chunks_exact(4)) {
+        for c in 0..3 {
+            let difference = a[c] as f64 - b[c] as f64;
+            squared_error += difference * difference;
+        }
+        count += 3;
+    }
+    if squared_error == 0. {
+        return f32::INFINITY;
+    }
+    (10. * (255. * 255. / (squared_error / count as f64)).log10()) as f32
+}
+
+/// The mean structural similarity between the luma of two RGBA8 images of `width` by `height`
+/// pixels, over a Gaussian window with a standard deviation of 1.5 pixels.
+///
+/// This follows Wang et al. (2004), except that the window is clamped at the edges of the image,
+/// so that even images smaller than the window get a score.
+fn ssim(a: &[u8], b: &[u8], width: u32, height: u32) -> f32 {
+    const C1: f64 = (0.01 * 255.) * (0.01 * 255.);
+    const C2: f64 = (0.03 * 255.) * (0.03 * 255.);
+
+    let (width, height) = (width as usize, height as usize);
+    let luma = |image: &[u8]| -> Vec<f64> {
+        image
+            .chunks_exact(4)
+            .map(|p| 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64)
+            .collect()
+    };
+    let (x, y) = (luma(a), luma(b));
+    let product =
+        |a: &[f64], b: &[f64]| -> Vec<f64> { a.iter().zip(b).map(|(a, b)| a * b).collect() };
+
+    let mu_x = blur(&x, width, height);
+    let mu_y = blur(&y, width, height);
+    let xx = blur(&product(&x, &x), width, height);
+    let yy = blur(&product(&y, &y), width, height);
+    let xy = blur(&product(&x, &y), width, height);
+
+    let mut sum = 0.;
+    for i in 0..width * height {
+        let (mu_x, mu_y) = (mu_x[i], mu_y[i]);
+        let sigma_x = xx[i] - mu_x * mu_x;
+        let sigma_y = yy[i] - mu_y * mu_y;
+        let sigma_xy = xy[i] - mu_x * mu_y;
+        sum += ((2. * mu_x * mu_y + C1) * (2. * sigma_xy + C2))
+            / ((mu_x * mu_x + mu_y * mu_y + C1) * (sigma_x + sigma_y + C2));
+    }
+    (sum / (width * height) as f64) as f32
+}
+
+/// Applies the 11x11 Gaussian window of [ssim], normalised over the part inside the image.
+fn blur(plane: &[f64], width: usize, height: usize) -> Vec<f64> {
+    const RADIUS: isize = 5;
+    let weights: Vec<f64> = (-RADIUS..=RADIUS)
+        .map(|d| (-(d * d) as f64 / (2. * 1.5 * 1.5)).exp())
+        .collect();
+
+    let pass = |input: &[f64], len: usize, step: usize, lines: usize, line_step: usize| {
+        let mut output = vec![0.; input.len()];
+        for line in 0..lines {
+            let start = line * line_step;
+            for i in 0..len as isize {
+                let (mut sum, mut total) = (0., 0.);
+                for (d, weight) in (-RADIUS..=RADIUS).zip(&weights) {
+                    let j = i + d;
+                    if 0 <= j && j < len as isize {
+                        sum += weight * input[start + j as usize * step];
+                        total += weight;
+                    }
+                }
+                output[start + i as usize * step] = sum / total;
+            }
+        }
+        output
+    };
+
+    let horizontal = pass(plane, width, 1, height, width);
+    pass(&horizontal, height, width, width, 1)
+}
+
+/// The mean CIEDE2000 color difference between two RGBA8 buffers.
+fn delta_e(a: &[u8], b: &[u8]) -> f32 {
+    let (mut sum, mut count) = (0., 0usize);
+    for (a, b) in a.chunks_exact(4).zip(b.chunks_exact(4)) {
+        sum += ciede2000(lab([a[0], a[1], a[2]]), lab([b[0], b[1], b[2]]));
+        count += 1;
+    }
+    (sum / count as f64) as f32
+}
+
+/// Converts an sRGB color to CIELAB, under the D65 white point.
+fn lab(srgb: [u8; 3]) -> [f64; 3] {
+    let [r, g, b] = srgb.map(|c| srgb_to_linear(c) as f64);
+    let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+    let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+
+    let f = |t: f64| {
+        const DELTA: f64 = 6. / 29.;
+        if t > DELTA * DELTA * DELTA {
+            t.cbrt()
+        } else {
+            t / (3. * DELTA * DELTA) + 4. / 29.
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116. * fy - 16., 500. * (fx - fy), 200. * (fy - fz)]
+}
+
+/// The CIEDE2000 color difference between two CIELAB colors, after Sharma et al. (2005).
+fn ciede2000([l1, a1, b1]: [f64; 3], [l2, a2, b2]: [f64; 3]) -> f64 {
+    use core::f64::consts::PI;
+    let pow7 = |x: f64| x.powi(7);
+    let degrees = |radians: f64| radians * 180. / PI;
+    let radians = |degrees: f64| degrees * PI / 180.;
+
+    let c_bar = ((a1 * a1 + b1 * b1).sqrt() + (a2 * a2 + b2 * b2).sqrt()) / 2.;
+    let g = 0.5 * (1. - (pow7(c_bar) / (pow7(c_bar) + pow7(25.))).sqrt());
+    let (a1, a2) = ((1. + g) * a1, (1. + g) * a2);
+    let (c1, c2) = ((a1 * a1 + b1 * b1).sqrt(), (a2 * a2 + b2 * b2).sqrt());
+    let hue = |b: f64, a: f64| {
+        if a == 0. && b == 0. {
+            0.
+        } else {
+            degrees(b.atan2(a)).rem_euclid(360.)
+        }
+    };
+    let (h1, h2) = (hue(b1, a1), hue(b2, a2));
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0. {
+        0.
+    } else if (h2 - h1).abs() <= 180. {
+        h2 - h1
+    } else if h2 - h1 > 180. {
+        h2 - h1 - 360.
+    } else {
+        h2 - h1 + 360.
+    };
+    let delta_h = 2. * (c1 * c2).sqrt() * radians(delta_h / 2.).sin();
+
+    let l_bar = (l1 + l2) / 2.;
+    let c_bar = (c1 + c2) / 2.;
+    let h_bar = if c1 * c2 == 0. {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180. {
+        (h1 + h2) / 2.
+    } else if h1 + h2 < 360. {
+        (h1 + h2 + 360.) / 2.
+    } else {
+        (h1 + h2 - 360.) / 2.
+    };
+
+    let t = 1. - 0.17 * radians(h_bar - 30.).cos()
+        + 0.24 * radians(2. * h_bar).cos()
+        + 0.32 * radians(3. * h_bar + 6.).cos()
+        - 0.20 * radians(4. * h_bar - 63.).cos();
+    let delta_theta = 30. * (-((h_bar - 275.) / 25.).powi(2)).exp();
+    let r_c = 2. * (pow7(c_bar) / (pow7(c_bar) + pow7(25.))).sqrt();
+    let l_offset = (l_bar - 50.) * (l_bar - 50.);
+    let s_l = 1. + 0.015 * l_offset / (20. + l_offset).sqrt();
+    let s_c = 1. + 0.045 * c_bar;
+    let s_h = 1. + 0.015 * c_bar * t;
+    let r_t = -radians(2. * delta_theta).sin() * r_c;
+
+    let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+    (l * l + c * c + h * h + r_t * c * h).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode;
+    use image::GenericImageView;
+
+    #[test]
+    fn ciede2000_reference_pairs() {
+        // From the test data of Sharma, Wu and Dalal (2005).
+        let pairs = [
+            ([50., 2.6772, -79.7751], [50., 0., -82.7485], 2.0425),
+            ([50., 0., 0.], [50., -1., 2.], 2.3669),
+            ([50., 2.49, -0.001], [50., -2.49, 0.0011], 7.2195),
+            ([50., 2.5, 0.], [73., 25., -18.], 27.1492),
+            (
+                [60.2574, -34.0099, 36.2677],
+                [60.4626, -34.1751, 39.4387],
+                1.2644,
+            ),
+            (
+                [22.7233, 20.0904, -46.694],
+                [23.0331, 14.973, -42.5619],
+                2.0373,
+            ),
+            (
+                [90.9257, -0.5406, -0.9208],
+                [88.6381, -0.8985, -0.7239],
+                1.5381,
+            ),
+        ];
+        for (lab1, lab2, expected) in pairs {
+            let delta_e = ciede2000(lab1, lab2);
+            assert!(
+                (delta_e - expected).abs() < 1e-4,
+                "{} vs {}",
+                delta_e,
+                expected
+            );
+            assert!((ciede2000(lab2, lab1) - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn lab_reference_colors() {
+        let close = |a: [f64; 3], b: [f64; 3]| (0..3).all(|c| (a[c] - b[c]).abs() < 0.05);
+        assert!(close(lab([255, 255, 255]), [100., 0., 0.]));
+        assert!(close(lab([0, 0, 0]), [0., 0., 0.]));
+        assert!(close(lab([255, 0, 0]), [53.24, 80.09, 67.2]));
+        assert!(close(lab([0, 0, 255]), [32.3, 79.19, -107.86]));
+    }
+
+    #[test]
+    fn identical_images() {
+        let img = image::open("data/octocat.png").unwrap();
+        let (width, height) = img.dimensions();
+        let img = img.to_rgba8();
+
+        let metrics = compare(&img, &img, width, height).unwrap();
+        assert_eq!(metrics.psnr, f32::INFINITY);
+        assert!((metrics.ssim - 1.).abs() < 1e-6);
+        assert_eq!(metrics.delta_e, 0.);
+    }
+
+    #[test]
+    fn more_components_are_more_faithful() {
+        let img = image::open("data/SIPI_Jelly_Beans.tiff").unwrap();
+        let (width, height) = img.dimensions();
+        let img = img.to_rgba8();
+
+        let coarse = encode(1, 1, width, height, &img).unwrap();
+        let fine = encode(9, 9, width, height, &img).unwrap();
+        for size in [None, Some(32)] {
+            let coarse = measure(&coarse, width, height, &img, size).unwrap();
+            let fine = measure(&fine, width, height, &img, size).unwrap();
+
+            assert!(fine.psnr > coarse.psnr, "{:?} vs {:?}", fine, coarse);
+            assert!(fine.ssim > coarse.ssim, "{:?} vs {:?}", fine, coarse);
+            assert!(fine.delta_e < coarse.delta_e, "{:?} vs {:?}", fine, coarse);
+        }
+    }
+
+    #[test]
+    fn bad_input() {
+        assert_eq!(
+            compare(&[0; 16], &[0; 12], 2, 2),
+            Err(Error::BufferSizeMismatch {
+                expected: 16,
+                actual: 12
+            })
+        );
+        assert_eq!(
+            compare(&[], &[], 0, 3),
+            Err(Error::InvalidDimensions {
+                width: 0,
+                height: 3
+            })
+        );
+        assert_eq!(
+            compare(&[], &[], 3, 0),
+            Err(Error::InvalidDimensions {
+                width: 3,
+                height: 0
+            })
+        );
+        assert!(matches!(
+            measure("LNAdAq", 2, 2, &[0; 16], None),
+            Err(Error::LengthMismatch { .. })
+        ));
+    }
+}
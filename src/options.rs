@@ -0,0 +1,314 @@
+use crate::util::srgb_to_linear;
+
+/// How the alpha channel of the input is taken into account when encoding.
+///
+/// Alpha is treated as straight (not premultiplied), as in the buffers of the `image` crate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Ignore the alpha channel, and use the color channels as-is.
+    #[default]
+    Ignore,
+    /// Composite every pixel over an opaque sRGB background color, in linear light.
+    Composite([u8; 3]),
+    /// Weigh the contribution of every pixel by its alpha, so that transparent pixels are left out.
+    ///
+    /// An image that is entirely transparent hashes as black.
+    Weighted,
+}
+
+impl AlphaMode {
+    /// Applies this mode to a row of linear RGBA pixels, leaving the result in the color channels.
+    ///
+    /// Returns the total weight of the row.
+    pub(crate) fn apply(self, linear: &mut [[f32; 4]]) -> f32 {
+        match self {
+            AlphaMode::Ignore => linear.len() as f32,
+            AlphaMode::Composite(background) => {
+                let background = background.map(srgb_to_linear);
+                for pixel in linear.iter_mut() {
+                    let alpha = pixel[3];
+                    for (c, background) in pixel[..3].iter_mut().zip(background) {
+                        *c = *c * alpha + background * (1. - alpha);
+                    }
+                }
+                linear.len() as f32
+            }
+            AlphaMode::Weighted => {
+                let mut weight = 0.;
+                for pixel in linear.iter_mut() {
+                    let alpha = pixel[3];
+                    pixel[0] *= alpha;
+                    pixel[1] *= alpha;
+                    pixel[2] *= alpha;
+                    weight += alpha;
+                }
+                weight
+            }
+        }
+    }
+
+    /// The weight of a single pixel that [apply][Self::apply] has been called on.
+    pub(crate) fn weight(self, pixel: &[f32; 4]) -> f32 {
+        match self {
+            AlphaMode::Ignore | AlphaMode::Composite(_) => 1.,
+            AlphaMode::Weighted => pixel[3],
+        }
+    }
+}
+
+/// Options that control how [encode_with_options][crate::encode_with_options] projects an image.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct EncodeOptions {
+    /// How the alpha channel of the input is taken into account.
+    pub alpha: AlphaMode,
+    /// The number of pixels that either edge of the image is box-filtered down to, at most, before
+    /// it is projected.
+    ///
+    /// Blurhash keeps almost no spatial detail, so this makes encoding large images much cheaper.
+    /// At 64 pixels, it has been measured to change every component of the hash by at most one
+    /// quantization step, for up to 9 components. Smaller sizes can change the hash a lot.
+    pub downsample: Option<u32>,
+}
+
+impl EncodeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how the alpha channel of the input is taken into account.
+    pub fn alpha(mut self, alpha: AlphaMode) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    /// Box-filters the image, in linear light, until neither edge is longer than `size` pixels
+    /// before encoding it. Each edge is reduced on its own, so a long and narrow image keeps its
+    /// detail across.
+    ///
+    /// 64 is a good choice for up to 9 components: at that size, every component of the hash has
+    /// been measured to change by at most one quantization step. That bound does not hold for
+    /// smaller sizes, which can give a completely different hash.
+    pub fn downsample(mut self, size: u32) -> Self {
+        self.downsample = Some(size);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AlphaMode, EncodeOptions};
+    use crate::{encode, encode_with_options, Blurhash, ImageView};
+    use image::GenericImageView;
+
+    fn encode_alpha(
+        components: (u32, u32),
+        width: u32,
+        height: u32,
+        data: &[u8],
+        alpha: AlphaMode,
+    ) -> String {
+        let options = EncodeOptions::new().alpha(alpha);
+        let view = ImageView::new(data, width, height);
+        encode_with_options(components.0, components.1, &view, &options).unwrap()
+    }
+
+    #[test]
+    fn opaque_image_is_unaffected() {
+        let img = image::open("data/SIPI_Jelly_Beans.tiff").unwrap();
+        let (width, height) = img.dimensions();
+        let img = img.to_rgba8();
+        let reference = encode(4, 3, width, height, &img).unwrap();
+
+        for alpha in [AlphaMode::Composite([12, 34, 56]), AlphaMode::Weighted] {
+            assert_eq!(encode_alpha((4, 3), width, height, &img, alpha), reference);
+        }
+    }
+
+    #[test]
+    fn transparent_image_composites_to_background() {
+        let garbage: Vec<u8> = (0..16 * 16).flat_map(|i| [i as u8, 0, 255, 0]).collect();
+        let background: Vec<u8> = (0..16 * 16).flat_map(|_| [40, 80, 120, 255]).collect();
+
+        assert_eq!(
+            encode_alpha(
+                (4, 3),
+                16,
+                16,
+                &garbage,
+                AlphaMode::Composite([40, 80, 120])
+            ),
+            encode(4, 3, 16, 16, &background).unwrap()
+        );
+        assert_eq!(
+            encode_alpha((4, 3), 16, 16, &garbage, AlphaMode::Weighted),
+            encode(4, 3, 16, 16, &[0; 16 * 16 * 4]).unwrap()
+        );
+    }
+
+    #[test]
+    fn transparent_logo() {
+        let img = image::open("data/wikipedia_logo.png").unwrap();
+        let (width, height) = img.dimensions();
+        let img = img.to_rgba8();
+        let reference = encode(4, 3, width, height, &img).unwrap();
+
+        // Cut out the white background, leaving magenta under the transparent pixels.
+        let mut transparent = img.clone();
+        for pixel in transparent.pixels_mut() {
+            if pixel.0 == [255, 255, 255, 255] {
+                pixel.0 = [255, 0, 255, 0];
+            }
+        }
+
+        assert_ne!(
+            encode_alpha((4, 3), width, height, &transparent, AlphaMode::Ignore),
+            reference
+        );
+        assert_eq!(
+            encode_alpha(
+                (4, 3),
+                width,
+                height,
+                &transparent,
+                AlphaMode::Composite([255, 255, 255])
+            ),
+            reference
+        );
+    }
+
+    /// The largest difference between any quantised channel of the two hashes.
+    fn quantisation_steps(a: &str, b: &str) -> u32 {
+        let (a, b): (Blurhash, Blurhash) = (a.parse().unwrap(), b.parse().unwrap());
+        let steps = |a: u32, b: u32, base: u32| {
+            let channels = |v: u32| [v / (base * base), v / base % base, v % base];
+            let (a, b) = (channels(a), channels(b));
+            (0..3).map(|c| a[c].abs_diff(b[c])).max().unwrap()
+        };
+
+        let mut max_steps = a
+            .quantised_maximum_value()
+            .abs_diff(b.quantised_maximum_value());
+        max_steps = max_steps.max(steps(a.quantised_dc(), b.quantised_dc(), 256));
+        for (&a, &b) in a.quantised_ac().iter().zip(b.quantised_ac()) {
+            max_steps = max_steps.max(steps(a, b, 19));
+        }
+        max_steps
+    }
+
+    /// The smallest and largest counts, a common one, and both extremes of the aspect ratio.
+    const COMPONENTS: [(u32, u32); 5] = [(1, 1), (4, 3), (9, 1), (1, 9), (9, 9)];
+
+    #[test]
+    fn downsampling_changes_at_most_one_step() {
+        for path in [
+            "data/octocat.png",
+            "data/wikipedia_logo.png",
+            "data/SIPI_Jelly_Beans.tiff",
+        ] {
+            let img = image::open(path).unwrap();
+            let (width, height) = img.dimensions();
+            let img = img.to_rgba8();
+            let view = ImageView::new(&img, width, height);
+
+            for (components_x, components_y) in COMPONENTS {
+                for alpha in [AlphaMode::Ignore, AlphaMode::Weighted] {
+                    let options = EncodeOptions::new().alpha(alpha);
+                    let full = encode_with_options(components_x, components_y, &view, &options);
+                    let options = options.downsample(64);
+                    let small = encode_with_options(components_x, components_y, &view, &options);
+
+                    let (full, small) = (full.unwrap(), small.unwrap());
+                    let steps = quantisation_steps(&full, &small);
+                    assert!(
+                        steps <= 1,
+                        "{} with {}x{} components: {} vs {} differ by {} steps",
+                        path,
+                        components_x,
+                        components_y,
+                        full,
+                        small,
+                        steps
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn downsampling_long_and_narrow_images() {
+        // Gradients across the short edge, which must not be averaged away.
+        let gradient = |width: u32, height: u32, across_x: bool| -> Vec<u8> {
+            (0..width * height)
+                .flat_map(|i| {
+                    let (position, len) = match across_x {
+                        true => (i % width, width),
+                        false => (i / width, height),
+                    };
+                    let value = (position * 255 / (len - 1)) as u8;
+                    [value, 255 - value, value / 2, 255]
+                })
+                .collect()
+        };
+
+        for &(width, height, across_x) in &[
+            (1000, 10, false),
+            (10, 1000, true),
+            (3000, 90, false),
+            (90, 3000, true),
+        ] {
+            let img = gradient(width, height, across_x);
+            let view = ImageView::new(&img, width, height);
+            for (components_x, components_y) in COMPONENTS {
+                let options = EncodeOptions::new();
+                let full = encode_with_options(components_x, components_y, &view, &options);
+                let options = options.downsample(64);
+                let small = encode_with_options(components_x, components_y, &view, &options);
+
+                let (full, small) = (full.unwrap(), small.unwrap());
+                let steps = quantisation_steps(&full, &small);
+                assert!(
+                    steps <= 1,
+                    "{}x{} with {}x{} components: {} vs {} differ by {} steps",
+                    width,
+                    height,
+                    components_x,
+                    components_y,
+                    full,
+                    small,
+                    steps
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn downsampling_small_images_is_exact() {
+        let img = image::open("data/octocat.png").unwrap();
+        let (width, height) = img.dimensions();
+        let img = img.to_rgba8();
+        let view = ImageView::new(&img, width, height);
+
+        let options = EncodeOptions::new().downsample(width.max(height));
+        assert_eq!(
+            encode_with_options(4, 3, &view, &options).unwrap(),
+            encode(4, 3, width, height, &img).unwrap()
+        );
+    }
+
+    #[test]
+    fn weighted_average_skips_transparent_pixels() {
+        let half: Vec<u8> = (0..16 * 16)
+            .flat_map(|i| match i % 16 < 8 {
+                true => [255, 0, 255, 0],
+                false => [10, 200, 30, 255],
+            })
+            .collect();
+        let solid: Vec<u8> = (0..16 * 16).flat_map(|_| [10, 200, 30, 255]).collect();
+
+        assert_eq!(
+            encode_alpha((1, 1), 16, 16, &half, AlphaMode::Weighted),
+            encode(1, 1, 16, 16, &solid).unwrap()
+        );
+    }
+}
@@ -0,0 +1,392 @@
+#[cfg(feature = "fixed-point")]
+use crate::util::linear_to_srgb_fixed;
+use crate::util::{linear_to_srgb, linear_to_srgb_f32, srgb_to_linear, srgb_to_linear_f32};
+use core::convert::TryInto;
+
+/// The memory layout of a single pixel.
+///
+/// Multi-byte channels are stored in native byte order, as in the buffers of the `image` crate.
+/// Integer formats hold sRGB encoded values, floating point formats hold linear light.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    /// 8-bit red, green, blue and alpha.
+    Rgba8,
+    /// 8-bit red, green and blue.
+    Rgb8,
+    /// 8-bit blue, green, red and alpha, as used by many capture APIs.
+    Bgra8,
+    /// 8-bit alpha, red, green and blue.
+    Argb8,
+    /// 8-bit grayscale.
+    Luma8,
+    /// 16-bit red, green, blue and alpha.
+    Rgba16,
+    /// 16-bit packed red (5 bits), green (6 bits) and blue (5 bits), as used by many embedded displays.
+    Rgb565,
+    /// 32-bit floating point red, green, blue and alpha in linear light.
+    Rgba32F,
+}
+
+impl PixelFormat {
+    /// The number of bytes a single pixel occupies.
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgba8 | PixelFormat::Bgra8 | PixelFormat::Argb8 => 4,
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Luma8 => 1,
+            PixelFormat::Rgba16 => 8,
+            PixelFormat::Rgb565 => 2,
+            PixelFormat::Rgba32F => 16,
+        }
+    }
+
+    /// Converts a row of pixels in this format to linear RGB, followed by a straight alpha in 0.0-1.0.
+    pub(crate) fn linearize_row(self, row: &[u8], linear: &mut [[f32; 4]]) {
+        let bytes_per_pixel = self.bytes_per_pixel();
+        let pixels = row.chunks_exact(bytes_per_pixel).zip(linear.iter_mut());
+
+        let rgba = |r, g, b, a: u8| {
+            [
+                srgb_to_linear(r),
+                srgb_to_linear(g),
+                srgb_to_linear(b),
+                a as f32 / 255.,
+            ]
+        };
+
+        match self {
+            PixelFormat::Rgba8 => pixels.for_each(|(p, l)| *l = rgba(p[0], p[1], p[2], p[3])),
+            PixelFormat::Rgb8 => pixels.for_each(|(p, l)| *l = rgba(p[0], p[1], p[2], 255)),
+            PixelFormat::Bgra8 => pixels.for_each(|(p, l)| *l = rgba(p[2], p[1], p[0], p[3])),
+            PixelFormat::Argb8 => pixels.for_each(|(p, l)| *l = rgba(p[1], p[2], p[3], p[0])),
+            PixelFormat::Luma8 => pixels.for_each(|(p, l)| *l = rgba(p[0], p[0], p[0], 255)),
+            PixelFormat::Rgba16 => pixels.for_each(|(p, l)| {
+                let channel = |c: usize| u16::from_ne_bytes(p[2 * c..][..2].try_into().unwrap());
+                *l = [
+                    srgb_to_linear_f32(channel(0) as f32 / 65535.),
+                    srgb_to_linear_f32(channel(1) as f32 / 65535.),
+                    srgb_to_linear_f32(channel(2) as f32 / 65535.),
+                    channel(3) as f32 / 65535.,
+                ]
+            }),
+            PixelFormat::Rgb565 => pixels.for_each(|(p, l)| {
+                let value = u16::from_ne_bytes([p[0], p[1]]);
+                let r = (value >> 11) as u8 & 0x1f;
+                let g = (value >> 5) as u8 & 0x3f;
+                let b = value as u8 & 0x1f;
+                *l = rgba(r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2, 255)
+            }),
+            PixelFormat::Rgba32F => pixels.for_each(|(p, l)| {
+                *l = [0, 1, 2, 3].map(|c| f32::from_ne_bytes(p[4 * c..][..4].try_into().unwrap()))
+            }),
+        }
+    }
+
+    /// Writes a row of linear RGB pixels in this format. Alpha, if any, is set to fully opaque.
+    pub(crate) fn write_row(self, linear: &[[f32; 3]], row: &mut [u8]) {
+        let bytes_per_pixel = self.bytes_per_pixel();
+        let pixels = row.chunks_exact_mut(bytes_per_pixel).zip(linear.iter());
+
+        let srgb = |l: &[f32; 3]| l.map(linear_to_srgb);
+
+        match self {
+            PixelFormat::Rgba8 => pixels.for_each(|(p, l)| {
+                let [r, g, b] = srgb(l);
+                p.copy_from_slice(&[r, g, b, 255]);
+            }),
+            PixelFormat::Rgb8 => pixels.for_each(|(p, l)| p.copy_from_slice(&srgb(l))),
+            PixelFormat::Bgra8 => pixels.for_each(|(p, l)| {
+                let [r, g, b] = srgb(l);
+                p.copy_from_slice(&[b, g, r, 255]);
+            }),
+            PixelFormat::Argb8 => pixels.for_each(|(p, l)| {
+                let [r, g, b] = srgb(l);
+                p.copy_from_slice(&[255, r, g, b]);
+            }),
+            PixelFormat::Luma8 => pixels.for_each(|(p, l)| {
+                // Relative luminance, as defined by Rec. 709 for linear light.
+                p[0] = linear_to_srgb(0.2126 * l[0] + 0.7152 * l[1] + 0.0722 * l[2]);
+            }),
+            PixelFormat::Rgba16 => pixels.for_each(|(p, l)| {
+                let [r, g, b] = l.map(|c| (linear_to_srgb_f32(c) * 65535. + 0.5) as u16);
+                for (c, value) in p.chunks_exact_mut(2).zip([r, g, b, u16::MAX]) {
+                    c.copy_from_slice(&value.to_ne_bytes());
+                }
+            }),
+            PixelFormat::Rgb565 => pixels.for_each(|(p, l)| {
+                let [r, g, b] = srgb(l).map(u16::from);
+                let r = (r * 31 + 127) / 255;
+                let g = (g * 63 + 127) / 255;
+                let b = (b * 31 + 127) / 255;
+                p.copy_from_slice(&(r << 11 | g << 5 | b).to_ne_bytes());
+            }),
+            PixelFormat::Rgba32F => pixels.for_each(|(p, l)| {
+                for (c, value) in p.chunks_exact_mut(4).zip([l[0], l[1], l[2], 1.]) {
+                    c.copy_from_slice(&value.to_ne_bytes());
+                }
+            }),
+        }
+    }
+
+    /// Writes a row of linear RGB pixels in this format, like [write_row][Self::write_row], from
+    /// fixed point numbers with `FIXED_LINEAR_BITS` fractional bits.
+    ///
+    /// Only `Rgba32F` uses floating point, as it stores floats. `Rgba16` carries 8 bits of sRGB.
+    #[cfg(feature = "fixed-point")]
+    pub(crate) fn write_row_fixed(self, linear: &[[i32; 3]], row: &mut [u8]) {
+        let bytes_per_pixel = self.bytes_per_pixel();
+        let pixels = row.chunks_exact_mut(bytes_per_pixel).zip(linear.iter());
+
+        let srgb = |l: &[i32; 3]| l.map(linear_to_srgb_fixed);
+
+        match self {
+            PixelFormat::Rgba8 => pixels.for_each(|(p, l)| {
+                let [r, g, b] = srgb(l);
+                p.copy_from_slice(&[r, g, b, 255]);
+            }),
+            PixelFormat::Rgb8 => pixels.for_each(|(p, l)| p.copy_from_slice(&srgb(l))),
+            PixelFormat::Bgra8 => pixels.for_each(|(p, l)| {
+                let [r, g, b] = srgb(l);
+                p.copy_from_slice(&[b, g, r, 255]);
+            }),
+            PixelFormat::Argb8 => pixels.for_each(|(p, l)| {
+                let [r, g, b] = srgb(l);
+                p.copy_from_slice(&[255, r, g, b]);
+            }),
+            PixelFormat::Luma8 => pixels.for_each(|(p, l)| {
+                // Relative luminance, as defined by Rec. 709, with weights in 1/65536ths.
+                let [r, g, b] = l.map(i64::from);
+                p[0] = linear_to_srgb_fixed(((13933 * r + 46871 * g + 4732 * b) >> 16) as i32);
+            }),
+            PixelFormat::Rgba16 => pixels.for_each(|(p, l)| {
+                let [r, g, b] = srgb(l).map(|c| u16::from(c) * 257);
+                for (c, value) in p.chunks_exact_mut(2).zip([r, g, b, u16::MAX]) {
+                    c.copy_from_slice(&value.to_ne_bytes());
+                }
+            }),
+            PixelFormat::Rgb565 => pixels.for_each(|(p, l)| {
+                let [r, g, b] = srgb(l).map(u16::from);
+                let r = (r * 31 + 127) / 255;
+                let g = (g * 63 + 127) / 255;
+                let b = (b * 31 + 127) / 255;
+                p.copy_from_slice(&(r << 11 | g << 5 | b).to_ne_bytes());
+            }),
+            PixelFormat::Rgba32F => pixels.for_each(|(p, l)| {
+                let one = (1 << crate::util::FIXED_LINEAR_BITS) as f32;
+                let [r, g, b] = l.map(|c| c as f32 / one);
+                for (c, value) in p.chunks_exact_mut(4).zip([r, g, b, 1.]) {
+                    c.copy_from_slice(&value.to_ne_bytes());
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PixelFormat;
+    use crate::util::{linear_to_srgb, srgb_to_linear};
+    use crate::{decode, decode_into_layout, encode, encode_view, ImageView};
+    use image::{EncodableLayout, GenericImageView};
+    use std::convert::TryInto;
+
+    const OCTOCAT: &str = "LNAdAqj[00aymkj[TKay9}ay-Sj[";
+
+    fn octocat() -> (u32, u32, Vec<u8>) {
+        let img = image::open("data/octocat.png").unwrap();
+        let (width, height) = img.dimensions();
+        (width, height, img.to_rgba8().into_raw())
+    }
+
+    fn encode_as(format: PixelFormat, width: u32, height: u32, data: &[u8]) -> String {
+        encode_view(4, 3, &ImageView::with_format(data, width, height, format)).unwrap()
+    }
+
+    fn shuffle(rgba: &[u8], f: impl Fn(&[u8]) -> Vec<u8>) -> Vec<u8> {
+        rgba.chunks_exact(4).flat_map(f).collect()
+    }
+
+    #[test]
+    fn rgb8() {
+        let (width, height, rgba) = octocat();
+        let data = shuffle(&rgba, |p| vec![p[0], p[1], p[2]]);
+        assert_eq!(encode_as(PixelFormat::Rgb8, width, height, &data), OCTOCAT);
+    }
+
+    #[test]
+    fn bgra8() {
+        let (width, height, rgba) = octocat();
+        let data = shuffle(&rgba, |p| vec![p[2], p[1], p[0], p[3]]);
+        assert_eq!(encode_as(PixelFormat::Bgra8, width, height, &data), OCTOCAT);
+    }
+
+    #[test]
+    fn argb8() {
+        let (width, height, rgba) = octocat();
+        let data = shuffle(&rgba, |p| vec![p[3], p[0], p[1], p[2]]);
+        assert_eq!(encode_as(PixelFormat::Argb8, width, height, &data), OCTOCAT);
+    }
+
+    #[test]
+    fn luma8() {
+        let img = image::open("data/octocat.png").unwrap();
+        let (width, height) = img.dimensions();
+        let luma = img.to_luma8();
+        let gray_rgba = image::DynamicImage::ImageLuma8(luma.clone()).to_rgba8();
+
+        assert_eq!(
+            encode_as(PixelFormat::Luma8, width, height, luma.as_bytes()),
+            encode(4, 3, width, height, gray_rgba.as_bytes()).unwrap()
+        );
+    }
+
+    #[test]
+    fn rgba16() {
+        let img = image::open("data/octocat.png").unwrap();
+        let (width, height) = img.dimensions();
+        let rgba16 = img.to_rgba16();
+        assert_eq!(
+            encode_as(PixelFormat::Rgba16, width, height, rgba16.as_bytes()),
+            OCTOCAT
+        );
+    }
+
+    #[test]
+    fn rgba32f() {
+        let (width, height, rgba) = octocat();
+        let data: Vec<u8> = rgba
+            .chunks_exact(4)
+            .flat_map(|p| {
+                [
+                    srgb_to_linear(p[0]),
+                    srgb_to_linear(p[1]),
+                    srgb_to_linear(p[2]),
+                    1.,
+                ]
+            })
+            .flat_map(f32::to_ne_bytes)
+            .collect();
+        assert_eq!(
+            encode_as(PixelFormat::Rgba32F, width, height, &data),
+            OCTOCAT
+        );
+    }
+
+    #[test]
+    fn strided_rgb8() {
+        let (width, height, rgba) = octocat();
+        let stride = width as usize * 3 + 1;
+        let mut data = vec![0; stride * height as usize];
+        for (src, dst) in rgba.chunks(width as usize * 4).zip(data.chunks_mut(stride)) {
+            let rgb = shuffle(src, |p| vec![p[0], p[1], p[2]]);
+            dst[..rgb.len()].copy_from_slice(&rgb);
+        }
+
+        let view = ImageView::with_layout(&data, width, height, stride, PixelFormat::Rgb8);
+        assert_eq!(encode_view(4, 3, &view).unwrap(), OCTOCAT);
+    }
+
+    fn decode_as(format: PixelFormat, width: u32, height: u32) -> Vec<u8> {
+        let mut pixels = vec![0; width as usize * height as usize * format.bytes_per_pixel()];
+        let stride = width as usize * format.bytes_per_pixel();
+        decode_into_layout(&mut pixels, stride, format, OCTOCAT, width, height, 1.).unwrap();
+        pixels
+    }
+
+    #[test]
+    fn decode_8bit_layouts() {
+        let rgba = decode(OCTOCAT, 32, 24, 1.).unwrap();
+
+        assert_eq!(decode_as(PixelFormat::Rgba8, 32, 24), rgba);
+        assert_eq!(
+            decode_as(PixelFormat::Rgb8, 32, 24),
+            shuffle(&rgba, |p| vec![p[0], p[1], p[2]])
+        );
+        assert_eq!(
+            decode_as(PixelFormat::Bgra8, 32, 24),
+            shuffle(&rgba, |p| vec![p[2], p[1], p[0], p[3]])
+        );
+        assert_eq!(
+            decode_as(PixelFormat::Argb8, 32, 24),
+            shuffle(&rgba, |p| vec![p[3], p[0], p[1], p[2]])
+        );
+    }
+
+    #[test]
+    fn decode_luma8() {
+        let gray = decode_as(PixelFormat::Luma8, 32, 24);
+        let rgba = decode(OCTOCAT, 32, 24, 1.).unwrap();
+
+        for (luma, p) in gray.iter().zip(rgba.chunks_exact(4)) {
+            let min = p[..3].iter().min().unwrap();
+            let max = p[..3].iter().max().unwrap();
+            assert!((min.saturating_sub(1)..=max.saturating_add(1)).contains(luma));
+        }
+    }
+
+    #[test]
+    fn decode_rgb565() {
+        let rgb565 = decode_as(PixelFormat::Rgb565, 32, 24);
+        let rgba = decode(OCTOCAT, 32, 24, 1.).unwrap();
+
+        for (p565, p) in rgb565.chunks_exact(2).zip(rgba.chunks_exact(4)) {
+            let value = u16::from_ne_bytes([p565[0], p565[1]]);
+            assert_eq!(value >> 11, (p[0] as u16 * 31 + 127) / 255);
+            assert_eq!((value >> 5) & 0x3f, (p[1] as u16 * 63 + 127) / 255);
+            assert_eq!(value & 0x1f, (p[2] as u16 * 31 + 127) / 255);
+        }
+    }
+
+    #[test]
+    fn decode_rgba16() {
+        let rgba16 = decode_as(PixelFormat::Rgba16, 32, 24);
+        let rgba = decode(OCTOCAT, 32, 24, 1.).unwrap();
+
+        for (c16, c) in rgba16.chunks_exact(2).zip(&rgba) {
+            let value = u16::from_ne_bytes([c16[0], c16[1]]);
+            assert!((((value as u32 + 128) / 257) as i32 - *c as i32).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn decode_rgba32f() {
+        let rgba32f = decode_as(PixelFormat::Rgba32F, 32, 24);
+        let rgba = decode(OCTOCAT, 32, 24, 1.).unwrap();
+
+        for (c32, c) in rgba32f.chunks_exact(4).zip(&rgba) {
+            let value = f32::from_ne_bytes(c32.try_into().unwrap());
+            assert_eq!(linear_to_srgb(value), *c);
+        }
+    }
+
+    #[test]
+    fn decode_strided_leaves_padding() {
+        let stride = 32 * 3 + 5;
+        let mut pixels = vec![0xa5; stride * 24];
+        decode_into_layout(&mut pixels, stride, PixelFormat::Rgb8, OCTOCAT, 32, 24, 1.).unwrap();
+
+        let packed = decode_as(PixelFormat::Rgb8, 32, 24);
+        for (row, packed) in pixels.chunks(stride).zip(packed.chunks(32 * 3)) {
+            assert_eq!(&row[..32 * 3], packed);
+            assert!(row[32 * 3..].iter().all(|&b| b == 0xa5));
+        }
+    }
+
+    #[test]
+    fn rgb565() {
+        let (width, height, rgba) = octocat();
+        let data = shuffle(&rgba, |p| {
+            let value = (p[0] as u16 >> 3) << 11 | (p[1] as u16 >> 2) << 5 | p[2] as u16 >> 3;
+            value.to_ne_bytes().to_vec()
+        });
+        let expanded = shuffle(&rgba, |p| {
+            let (r, g, b) = (p[0] >> 3, p[1] >> 2, p[2] >> 3);
+            vec![r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2, 255]
+        });
+
+        assert_eq!(
+            encode_as(PixelFormat::Rgb565, width, height, &data),
+            encode(4, 3, width, height, &expanded).unwrap()
+        );
+    }
+}
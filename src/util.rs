@@ -1,16 +1,54 @@
 include!(concat!(env!("OUT_DIR"), "/srgb_lookup.rs"));
 
+#[cfg(feature = "std")]
+mod float {
+    pub fn cos(value: f32) -> f32 {
+        value.cos()
+    }
+
+    pub fn floor(value: f32) -> f32 {
+        value.floor()
+    }
+
+    pub fn ln(value: f32) -> f32 {
+        value.ln()
+    }
+
+    pub fn powf(value: f32, exp: f32) -> f32 {
+        value.powf(exp)
+    }
+
+    pub fn sqrt(value: f32) -> f32 {
+        value.sqrt()
+    }
+
+    #[cfg(not(feature = "fast-linear-to-srgb"))]
+    pub fn round(value: f32) -> f32 {
+        value.round()
+    }
+}
+
+/// Without `std`, `core` lacks the transcendental functions, so `libm` provides them instead.
+#[cfg(not(feature = "std"))]
+mod float {
+    #[cfg(not(feature = "fast-linear-to-srgb"))]
+    pub use libm::roundf as round;
+    pub use libm::{cosf as cos, floorf as floor, logf as ln, powf, sqrtf as sqrt};
+}
+
+pub use float::*;
+
 /// linear 0.0-1.0 floating point to srgb 0-255 integer conversion.
 #[cfg(not(feature = "fast-linear-to-srgb"))]
 pub fn linear_to_srgb(value: f32) -> u8 {
     let v = value.clamp(0., 1.);
     if v <= 0.003_130_8 {
-        (v * 12.92 * 255. + 0.5).round() as u8
+        round(v * 12.92 * 255. + 0.5) as u8
     } else {
         // The original C implementation uses this formula:
         // ((1.055 * f32::powf(v, 1. / 2.4) - 0.055) * 255. + 0.5).round() as u8
         // But we can distribute the latter multiplication, to reduce the number of operations:
-        ((1.055 * 255.) * f32::powf(v, 1. / 2.4) - (0.055 * 255. - 0.5)).round() as u8
+        round((1.055 * 255.) * powf(v, 1. / 2.4) - (0.055 * 255. - 0.5)) as u8
     }
 }
 
@@ -23,11 +61,44 @@ pub fn linear_to_srgb(value: f32) -> u8 {
     LINEAR_TO_SRGB_LOOKUP[index]
 }
 
+/// linear 0.0-1.0 floating point to srgb 0.0-1.0 floating point conversion.
+pub fn linear_to_srgb_f32(value: f32) -> f32 {
+    let v = value.clamp(0., 1.);
+    if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * powf(v, 1. / 2.4) - 0.055
+    }
+}
+
 /// srgb 0-255 integer to linear 0.0-1.0 floating point conversion.
 pub fn srgb_to_linear(value: u8) -> f32 {
     SRGB_LOOKUP[value as usize]
 }
 
+/// srgb 0.0-1.0 floating point to linear 0.0-1.0 floating point conversion.
+pub fn srgb_to_linear_f32(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        powf((value + 0.055) / 1.055, 2.4)
+    }
+}
+
+/// srgb 0-255 integer to linear fixed point conversion.
+#[cfg(feature = "fixed-point")]
+pub fn srgb_to_linear_fixed(value: u8) -> i32 {
+    SRGB_FIXED_LOOKUP[value as usize]
+}
+
+/// linear fixed point to srgb 0-255 integer conversion, which rounds like `linear_to_srgb`
+/// without the lookup table.
+#[cfg(feature = "fixed-point")]
+pub fn linear_to_srgb_fixed(value: i32) -> u8 {
+    let value = value.clamp(0, 1 << FIXED_LINEAR_BITS);
+    (LINEAR_TO_SRGB_THRESHOLDS.partition_point(|&threshold| threshold <= value) - 1) as u8
+}
+
 pub fn sign_pow(val: f32, exp: f32) -> f32 {
-    f32::copysign(f32::powf(val.abs(), exp), val)
+    f32::copysign(powf(val.abs(), exp), val)
 }
@@ -0,0 +1,131 @@
+use crate::{Blurhash, Error, Field};
+
+/// What [validate][crate::validate] found out about a well-formed blurhash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct ValidatedInfo {
+    /// The number of horizontal components, 1-9.
+    pub components_x: u32,
+    /// The number of vertical components, 1-9.
+    pub components_y: u32,
+}
+
+/// The largest DC value, a 24 bit sRGB color.
+pub(crate) const MAX_DC: u32 = (1 << 24) - 1;
+/// The largest AC value, three channels quantised to 19 levels each.
+const MAX_AC: u32 = 19 * 19 * 19 - 1;
+
+/// Checks that a blurhash is well-formed, without decoding it.
+///
+/// Besides the checks that [decode][crate::decode] does, this rejects hashes that only decode
+/// because out of range values get truncated: size flags for more than 9 vertical components,
+/// DC values beyond 24 bits and AC values beyond the 19 quantisation levels per channel. Every
+/// hash that an encoder produces passes.
+///
+/// ```
+/// let info = blurhash::validate("LNAdAqj[00aymkj[TKay9}ay-Sj[").unwrap();
+/// assert_eq!((info.components_x, info.components_y), (4, 3));
+///
+/// assert!(blurhash::validate("LNAdAq").is_err());
+/// ```
+pub fn validate(blurhash: &str) -> Result<ValidatedInfo, Error> {
+    let parsed: Blurhash = blurhash.parse()?;
+
+    let (components_x, components_y) = parsed.components();
+    if components_y > 9 {
+        return Err(out_of_range(Field::SizeFlag));
+    }
+    if parsed.quantised_dc() > MAX_DC {
+        return Err(out_of_range(Field::Dc));
+    }
+    if let Some(index) = parsed.quantised_ac().iter().position(|&ac| ac > MAX_AC) {
+        return Err(out_of_range(Field::Ac(index)));
+    }
+
+    Ok(ValidatedInfo {
+        components_x,
+        components_y,
+    })
+}
+
+pub(crate) fn out_of_range(field: Field) -> Error {
+    Error::ValueOutOfRange {
+        offset: field.offset(),
+        field,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate;
+    use crate::{Error, Field};
+    use proptest::prelude::*;
+
+    #[test]
+    fn encoded_hashes_are_valid() {
+        let image: Vec<u8> = (0..16 * 16 * 4).map(|i| (i * 7 % 256) as u8).collect();
+        for (components_x, components_y) in [(1, 1), (4, 3), (9, 9), (2, 7)] {
+            let hash = crate::encode(components_x, components_y, 16, 16, &image).unwrap();
+            let info = validate(&hash).unwrap();
+            assert_eq!(
+                (info.components_x, info.components_y),
+                (components_x, components_y)
+            );
+        }
+    }
+
+    #[test]
+    fn out_of_range() {
+        // Size flag 81 would mean 10 vertical components.
+        let mut hash = String::from("}");
+        hash.push_str(&"0".repeat(3 + 2 * 10));
+        assert_eq!(
+            validate(&hash),
+            Err(Error::ValueOutOfRange {
+                offset: 0,
+                field: Field::SizeFlag
+            })
+        );
+
+        // "~~~~" is 83^4 - 1, which does not fit in 24 bits.
+        assert_eq!(
+            validate("00~~~~"),
+            Err(Error::ValueOutOfRange {
+                offset: 2,
+                field: Field::Dc
+            })
+        );
+        // "~~" is 83^2 - 1, more than 19^3 - 1.
+        assert_eq!(
+            validate("100000~~"),
+            Err(Error::ValueOutOfRange {
+                offset: 6,
+                field: Field::Ac(0)
+            })
+        );
+        assert!(validate("100000Sj").is_ok());
+
+        assert!(matches!(validate("LNAdA"), Err(Error::HashTooShort)));
+        assert!(matches!(
+            validate("ͱZ0000"),
+            Err(Error::InvalidAscii { offset: 0 })
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn validate_doesnt_panic(blurhash in any::<String>()) {
+            let _ = validate(&blurhash);
+        }
+
+        #[test]
+        fn validate_base83_doesnt_panic(
+            blurhash in "[0-9A-Za-z#$%*+,-.:;=?@\\[\\]^_{|}~\"]{0,200}",
+        ) {
+            // Whatever passes validation decodes.
+            if validate(&blurhash).is_ok() {
+                prop_assert!(crate::decode(&blurhash, 4, 4, 1.).is_ok());
+            }
+        }
+    }
+}
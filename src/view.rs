@@ -0,0 +1,133 @@
+use crate::{Error, PixelFormat};
+
+/// A borrowed image whose rows may be padded, or which may be a region of a larger frame.
+///
+/// Rows start `stride` bytes apart. Only the first `width * format.bytes_per_pixel()` bytes of every
+/// row are read, so the last row does not need to carry any padding.
+#[derive(Clone, Copy, Debug)]
+pub struct ImageView<'a> {
+    data: &'a [u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    format: PixelFormat,
+}
+
+impl<'a> ImageView<'a> {
+    /// Creates a view of a tightly packed RGBA8 buffer.
+    pub fn new(data: &'a [u8], width: u32, height: u32) -> Self {
+        Self::with_stride(data, width, height, width as usize * 4)
+    }
+
+    /// Creates a view of an RGBA8 buffer whose rows start `stride` bytes apart.
+    pub fn with_stride(data: &'a [u8], width: u32, height: u32, stride: usize) -> Self {
+        Self::with_layout(data, width, height, stride, PixelFormat::Rgba8)
+    }
+
+    /// Creates a view of a tightly packed buffer of pixels in the given format.
+    pub fn with_format(data: &'a [u8], width: u32, height: u32, format: PixelFormat) -> Self {
+        let stride = width as usize * format.bytes_per_pixel();
+        Self::with_layout(data, width, height, stride, format)
+    }
+
+    /// Creates a view of a buffer of pixels in the given format, whose rows start `stride` bytes apart.
+    pub fn with_layout(
+        data: &'a [u8],
+        width: u32,
+        height: u32,
+        stride: usize,
+        format: PixelFormat,
+    ) -> Self {
+        ImageView {
+            data,
+            width,
+            height,
+            stride,
+            format,
+        }
+    }
+
+    /// Returns a view of the `width` by `height` region whose top-left corner is at `(x, y)`.
+    ///
+    /// Returns `None` if the region does not lie entirely within this view.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Option<Self> {
+        if x.checked_add(width)? > self.width || y.checked_add(height)? > self.height {
+            return None;
+        }
+
+        let offset = (y as usize)
+            .saturating_mul(self.stride)
+            .saturating_add(x as usize * self.format.bytes_per_pixel());
+        Some(ImageView {
+            data: self.data.get(offset..).unwrap_or_default(),
+            width,
+            height,
+            ..*self
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The distance between the starts of two consecutive rows, in bytes.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// The number of bytes a row of pixels occupies, without any padding.
+    pub(crate) fn row_len(&self) -> usize {
+        self.width as usize * self.format.bytes_per_pixel()
+    }
+
+    pub(crate) fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The number of bytes that the pixel rows of this view span.
+    pub(crate) fn required_len(&self) -> usize {
+        match self.height {
+            0 => 0,
+            height => (height as usize - 1)
+                .saturating_mul(self.stride)
+                .saturating_add(self.row_len()),
+        }
+    }
+
+    /// Returns the pixels of row `y`, without any padding.
+    pub(crate) fn row(&self, y: usize) -> &'a [u8] {
+        &self.data[y * self.stride..][..self.row_len()]
+    }
+}
+
+/// The length of a tightly packed `width` by `height` RGBA8 image, which must not exceed
+/// `isize::MAX`, the largest allocation there can be.
+pub(crate) fn rgba_len(width: u32, height: u32) -> Result<usize, Error> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|len| len.checked_mul(4))
+        .filter(|&len| len <= isize::MAX as usize)
+        .ok_or(Error::InvalidDimensions { width, height })
+}
+
+/// Checks that `pixels` holds exactly a `width` by `height` RGBA8 image.
+pub(crate) fn check_rgba_len(pixels: &[u8], width: u32, height: u32) -> Result<(), Error> {
+    let expected = (width as usize)
+        .saturating_mul(height as usize)
+        .saturating_mul(4);
+    if pixels.len() != expected {
+        return Err(Error::BufferSizeMismatch {
+            expected,
+            actual: pixels.len(),
+        });
+    }
+    Ok(())
+}
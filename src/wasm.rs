@@ -0,0 +1,84 @@
+//! Bindings for JavaScript, through `wasm-bindgen`.
+//!
+//! Pixels go in and out as `Uint8ClampedArray`s of RGBA8, the layout of `ImageData`:
+//!
+//! ```js
+//! import { decode, encode } from "blurhash";
+//!
+//! const pixels = decode("LNAdAqj[00aymkj[TKay9}ay-Sj[", 32, 32, 1.0);
+//! context.putImageData(new ImageData(pixels, 32, 32), 0, 0);
+//!
+//! const image = context.getImageData(0, 0, width, height);
+//! const hash = encode(image.data, image.width, image.height, 4, 3);
+//! ```
+//!
+//! Errors are thrown as JavaScript `Error`s.
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+
+/// Calculates the blurhash of an RGBA8 image using the given x and y component counts.
+#[wasm_bindgen]
+pub fn encode(
+    pixels: Clamped<Vec<u8>>,
+    width: u32,
+    height: u32,
+    components_x: u32,
+    components_y: u32,
+) -> Result<String, JsError> {
+    Ok(crate::encode(
+        components_x,
+        components_y,
+        width,
+        height,
+        &pixels,
+    )?)
+}
+
+/// Decodes the given blurhash to an RGBA8 image of the specified size.
+///
+/// The punch parameter can be used to de- or increase the contrast of the
+/// resulting image.
+#[wasm_bindgen]
+pub fn decode(
+    blurhash: &str,
+    width: u32,
+    height: u32,
+    punch: f32,
+) -> Result<Clamped<Vec<u8>>, JsError> {
+    Ok(Clamped(crate::decode(blurhash, width, height, punch)?))
+}
+
+/// The outcome of [is_blurhash_valid].
+#[wasm_bindgen(getter_with_clone)]
+pub struct Validation {
+    /// Whether the blurhash is valid.
+    pub result: bool,
+    /// Why the blurhash is invalid, if it is.
+    #[wasm_bindgen(js_name = errorReason)]
+    pub error_reason: Option<String>,
+}
+
+/// Checks that a blurhash is well-formed, without decoding it.
+///
+/// See [validate][crate::validate].
+#[wasm_bindgen(js_name = isBlurhashValid)]
+pub fn is_blurhash_valid(blurhash: &str) -> Validation {
+    match crate::validate(blurhash) {
+        Ok(_) => Validation {
+            result: true,
+            error_reason: None,
+        },
+        Err(e) => Validation {
+            result: false,
+            error_reason: Some(e.to_string()),
+        },
+    }
+}
+
+/// Returns the average color of the given blurhash as sRGB `[r, g, b]`, without decoding it.
+#[wasm_bindgen(js_name = averageColor)]
+pub fn average_color(blurhash: &str) -> Result<Vec<u8>, JsError> {
+    Ok(crate::average_color(blurhash)?.to_vec())
+}
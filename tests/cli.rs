@@ -0,0 +1,255 @@
+#![cfg(feature = "cli")]
+
+use std::process::Command;
+
+fn blurhash() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_blurhash"))
+}
+
+#[test]
+fn encode() {
+    let output = blurhash()
+        .args(["encode", "data/octocat.png", "-x", "4", "-y", "3"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"LNAdAqj[00aymkj[TKay9}ay-Sj[\n");
+}
+
+#[test]
+fn decode() {
+    let path = std::env::temp_dir().join(format!("blurhash-cli-decode-{}.png", std::process::id()));
+    let status = blurhash()
+        .args([
+            "decode",
+            "LNAdAqj[00aymkj[TKay9}ay-Sj[",
+            "-w",
+            "64",
+            "-h",
+            "48",
+        ])
+        .arg("-o")
+        .arg(&path)
+        .args(["--punch", "1.2"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let image = image::open(&path).unwrap().to_rgba8();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        image.into_raw(),
+        blurhash::decode("LNAdAqj[00aymkj[TKay9}ay-Sj[", 64, 48, 1.2).unwrap()
+    );
+}
+
+#[test]
+fn inspect() {
+    let output = blurhash()
+        .args(["inspect", "LNAdAqj[00aymkj[TKay9}ay-Sj["])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("components: 4x3\n"));
+    assert!(stdout.contains("average color: #5b5c5f\n"));
+    assert!(stdout.contains("maximum AC: 23 (0.1446)\n"));
+}
+
+#[test]
+fn inspect_metrics() {
+    let output = blurhash()
+        .args(["inspect", "LNAdAqj[00aymkj[TKay9}ay-Sj["])
+        .args(["--image", "data/octocat.png", "--size", "32"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    let image = image::open("data/octocat.png").unwrap().to_rgba8();
+    let metrics = blurhash::metrics::measure(
+        "LNAdAqj[00aymkj[TKay9}ay-Sj[",
+        image.width(),
+        image.height(),
+        &image,
+        Some(32),
+    )
+    .unwrap();
+    assert!(stdout.contains(&format!("PSNR: {:.2} dB\n", metrics.psnr)));
+    assert!(stdout.contains(&format!("SSIM: {:.4}\n", metrics.ssim)));
+    assert!(stdout.contains(&format!("mean ΔE2000: {:.2}\n", metrics.delta_e)));
+}
+
+#[test]
+fn invalid_hash() {
+    let output = blurhash().args(["inspect", "LNAdAq"]).output().unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("error: blurhash length mismatch"));
+}
+
+/// Creates a scratch directory with a few images, a broken image and a non-image file.
+fn batch_tree(name: &str) -> std::path::PathBuf {
+    let root = std::env::temp_dir().join(format!("blurhash-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("nested")).unwrap();
+    std::fs::copy("data/octocat.png", root.join("octocat.png")).unwrap();
+    std::fs::copy("data/wikipedia_logo.png", root.join("nested/logo.png")).unwrap();
+    std::fs::write(root.join("broken.png"), b"not a png").unwrap();
+    std::fs::write(root.join("notes.txt"), b"not an image").unwrap();
+    root
+}
+
+fn batch(root: &std::path::Path, manifest: &std::path::Path) -> std::process::Output {
+    blurhash()
+        .arg("batch")
+        .arg(root)
+        .arg("-o")
+        .arg(manifest)
+        .args(["-j", "2"])
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn batch_jsonl() {
+    let root = batch_tree("jsonl");
+    let manifest = root.with_extension("jsonl");
+    let _ = std::fs::remove_file(&manifest);
+
+    let output = batch(&root, &manifest);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!output.status.success());
+    assert!(stderr.contains("broken.png"), "{}", stderr);
+    assert!(stderr.contains("hashed 2, skipped 0 already in the manifest, 1 failed"));
+
+    let mut lines: Vec<serde_json::Value> = std::fs::read_to_string(&manifest)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    lines.sort_by_key(|line| line["path"].as_str().unwrap().to_owned());
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[1]["path"], "octocat.png");
+    assert_eq!(lines[1]["hash"], "LNAdAqj[00aymkj[TKay9}ay-Sj[");
+    assert_eq!(lines[1]["average_color"], "#5b5c5f");
+    assert!(lines[1]["width"].as_u64().unwrap() > 0);
+    assert_eq!(
+        lines[0]["path"],
+        std::path::Path::new("nested")
+            .join("logo.png")
+            .to_str()
+            .unwrap()
+    );
+
+    // A second run only retries the broken image.
+    std::fs::remove_file(root.join("broken.png")).unwrap();
+    let output = batch(&root, &manifest);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("hashed 0, skipped 2 already in the manifest, 0 failed"));
+    assert_eq!(
+        std::fs::read_to_string(&manifest).unwrap().lines().count(),
+        2
+    );
+
+    std::fs::remove_dir_all(&root).unwrap();
+    std::fs::remove_file(&manifest).unwrap();
+}
+
+#[test]
+fn batch_csv_resumes() {
+    let root = batch_tree("csv");
+    std::fs::remove_file(root.join("broken.png")).unwrap();
+    let manifest = root.with_extension("csv");
+    std::fs::write(
+        &manifest,
+        "path,width,height,hash,average_color\noctocat.png,1,1,L00000fQfQfQfQfQfQfQfQfQfQfQ,#000000\n",
+    )
+    .unwrap();
+
+    let output = batch(&root, &manifest);
+    assert!(output.status.success());
+
+    let manifest_contents = std::fs::read_to_string(&manifest).unwrap();
+    let lines: Vec<&str> = manifest_contents.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[2].starts_with("nested"));
+    assert!(
+        lines[2].contains(",LLO:@T~q%Mj]t7ofIUof?bM{?bM{,"),
+        "{}",
+        lines[2]
+    );
+
+    std::fs::remove_dir_all(&root).unwrap();
+    std::fs::remove_file(&manifest).unwrap();
+}
+
+#[test]
+fn batch_resumes_after_truncated_entry() {
+    let root = batch_tree("truncated");
+    std::fs::remove_file(root.join("broken.png")).unwrap();
+
+    let jsonl = root.with_extension("jsonl");
+    std::fs::write(
+        &jsonl,
+        "{\"path\":\"octocat.png\",\"width\":1,\"height\":1,\"hash\":\"L00000fQfQfQfQfQfQfQfQfQfQfQ\",\"average_color\":\"#000000\"}\n{\"path\":\"nested/lo",
+    )
+    .unwrap();
+    let output = batch(&root, &jsonl);
+    assert!(output.status.success(), "{:?}", output);
+    let contents = std::fs::read_to_string(&jsonl).unwrap();
+    let lines: Vec<serde_json::Value> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["hash"], "L00000fQfQfQfQfQfQfQfQfQfQfQ");
+    assert_eq!(lines[1]["hash"], "LLO:@T~q%Mj]t7ofIUof?bM{?bM{");
+
+    let csv = root.with_extension("csv");
+    std::fs::write(
+        &csv,
+        "path,width,height,hash,average_color\noctocat.png,1,1,L00000fQfQfQfQfQfQfQfQfQfQfQ,#000000\nnested",
+    )
+    .unwrap();
+    let output = batch(&root, &csv);
+    assert!(output.status.success(), "{:?}", output);
+    let contents = std::fs::read_to_string(&csv).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(
+        lines[2].contains(",LLO:@T~q%Mj]t7ofIUof?bM{?bM{,"),
+        "{}",
+        lines[2]
+    );
+
+    std::fs::remove_dir_all(&root).unwrap();
+    std::fs::remove_file(&jsonl).unwrap();
+    std::fs::remove_file(&csv).unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn batch_does_not_follow_directory_links() {
+    let root = batch_tree("links");
+    std::fs::remove_file(root.join("broken.png")).unwrap();
+    std::os::unix::fs::symlink(&root, root.join("nested/cycle")).unwrap();
+    let manifest = root.with_extension("jsonl");
+    let _ = std::fs::remove_file(&manifest);
+
+    let output = batch(&root, &manifest);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("hashed 2, skipped 0 already in the manifest, 0 failed"));
+
+    std::fs::remove_dir_all(&root).unwrap();
+    std::fs::remove_file(&manifest).unwrap();
+}
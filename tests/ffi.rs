@@ -0,0 +1,115 @@
+//! Builds the crate as a static library, and checks that the C harness in `tests/ffi` compiles
+//! against `include/blurhash.h` and produces the same output as the Rust API.
+#![cfg(all(feature = "ffi", unix))]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+const OCTOCAT: &str = "LNAdAqj[00aymkj[TKay9}ay-Sj[";
+
+fn target_dir() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi")
+}
+
+/// Compiles the harness once, linking it against a static build of the library.
+fn harness() -> &'static Path {
+    static HARNESS: OnceLock<PathBuf> = OnceLock::new();
+    HARNESS.get_or_init(|| {
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let target_dir = target_dir();
+
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+        let status = Command::new(cargo)
+            .args([
+                "rustc",
+                "--lib",
+                "--crate-type",
+                "staticlib",
+                "--features",
+                "ffi",
+            ])
+            .arg("--manifest-path")
+            .arg(manifest_dir.join("Cargo.toml"))
+            .arg("--target-dir")
+            .arg(&target_dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "building the static library failed");
+
+        let harness = target_dir.join("harness");
+        let mut cc = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".into()));
+        cc.args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-o"])
+            .arg(&harness)
+            .arg("-I")
+            .arg(manifest_dir.join("include"))
+            .arg(manifest_dir.join("tests/ffi/harness.c"))
+            .arg(target_dir.join("debug/libblurhash.a"));
+        if cfg!(target_os = "linux") {
+            cc.args(["-lpthread", "-ldl", "-lm"]);
+        }
+        assert!(
+            cc.status().unwrap().success(),
+            "compiling the harness failed"
+        );
+
+        harness
+    })
+}
+
+fn run(blurhash: &str, width: u32, height: u32, punch: f32) -> (i32, String, PathBuf) {
+    let output = target_dir().join(format!("{}x{}-{}.rgba", width, height, punch));
+    let result = Command::new(harness())
+        .arg(blurhash)
+        .arg(width.to_string())
+        .arg(height.to_string())
+        .arg(punch.to_string())
+        .arg(&output)
+        .output()
+        .unwrap();
+    (
+        result.status.code().unwrap(),
+        String::from_utf8(result.stdout).unwrap(),
+        output,
+    )
+}
+
+#[test]
+fn decode_matches_rust() {
+    for (width, height, punch) in [(32, 32, 1.), (17, 63, 1.), (1, 1, 0.5), (100, 40, 2.)] {
+        let (code, stdout, output) = run(OCTOCAT, width, height, punch);
+        assert_eq!(code, 0, "{}", stdout);
+
+        let pixels = std::fs::read(output).unwrap();
+        let expected = blurhash::decode(OCTOCAT, width, height, punch).unwrap();
+        assert_eq!(pixels, expected);
+
+        let encoded = blurhash::encode(4, 3, width, height, &expected).unwrap();
+        assert_eq!(stdout, format!("components: 4x3\nencoded: {}\n", encoded));
+    }
+}
+
+#[test]
+fn errors_map_to_status_codes() {
+    use blurhash::ffi::BlurhashStatus;
+
+    let cases = [
+        ("LNAdA", 32, BlurhashStatus::HashTooShort),
+        (
+            "LNAdAqj[00aymkj[TKay9}ay-Sj",
+            32,
+            BlurhashStatus::LengthMismatch,
+        ),
+        (
+            "LNAdAqj[00aymkj[TKay9}ay-Sj\"",
+            32,
+            BlurhashStatus::InvalidBase83,
+        ),
+        ("00~~~~", 32, BlurhashStatus::ValueOutOfRange),
+        (OCTOCAT, 0, BlurhashStatus::InvalidDimensions),
+    ];
+    for (blurhash, width, status) in cases {
+        let (code, stdout, _) = run(blurhash, width, 32, 1.);
+        assert_eq!(code, status as i32, "{}: {}", blurhash, stdout);
+    }
+}
@@ -0,0 +1,85 @@
+/*
+ * Exercises the C API of blurhash-rs.
+ *
+ * Usage: harness <blurhash> <width> <height> <punch> <output>
+ *
+ * Validates and decodes the blurhash, writes the raw RGBA8 pixels to <output>, and prints the
+ * components and the blurhash of the decoded pixels. On failure, prints the status and exits
+ * with it.
+ */
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "blurhash.h"
+
+static int fail(const char *call, BlurhashStatus status)
+{
+    printf("%s: %d\n", call, (int)status);
+    return (int)status;
+}
+
+int main(int argc, char **argv)
+{
+    if (argc != 6) {
+        fprintf(stderr, "usage: %s <blurhash> <width> <height> <punch> <output>\n", argv[0]);
+        return 100;
+    }
+    const char *hash = argv[1];
+    uint32_t width = (uint32_t)strtoul(argv[2], NULL, 10);
+    uint32_t height = (uint32_t)strtoul(argv[3], NULL, 10);
+    float punch = strtof(argv[4], NULL);
+
+    uint32_t components_x, components_y;
+    BlurhashStatus status = blurhash_validate(hash, &components_x, &components_y);
+    if (status != BLURHASH_STATUS_OK) {
+        return fail("blurhash_validate", status);
+    }
+    printf("components: %ux%u\n", components_x, components_y);
+
+    size_t len = (size_t)width * height * 4;
+    /* malloc(0) may return NULL, which would be reported as a null pointer instead. */
+    uint8_t *pixels = malloc(len ? len : 1);
+    if (pixels == NULL) {
+        perror("malloc");
+        return 102;
+    }
+
+    int result = 0;
+    status = blurhash_decode_into(hash, width, height, punch, pixels, len);
+    if (status != BLURHASH_STATUS_OK) {
+        result = fail("blurhash_decode_into", status);
+        goto out;
+    }
+
+    FILE *output = fopen(argv[5], "wb");
+    if (output == NULL || fwrite(pixels, 1, len, output) != len || fclose(output) != 0) {
+        perror(argv[5]);
+        result = 101;
+        goto out;
+    }
+
+    char encoded[BLURHASH_MAX_LEN + 1];
+    status = blurhash_encode(components_x, components_y, width, height, pixels, len, encoded,
+                             sizeof(encoded));
+    if (status != BLURHASH_STATUS_OK) {
+        result = fail("blurhash_encode", status);
+        goto out;
+    }
+    printf("encoded: %s\n", encoded);
+
+    /* A buffer that is one byte short is rejected rather than overrun. */
+    status = blurhash_decode_into(hash, width, height, punch, pixels, len - 1);
+    if (status != BLURHASH_STATUS_BUFFER_SIZE_MISMATCH) {
+        result = fail("blurhash_decode_into with a short buffer", status);
+        goto out;
+    }
+    status = blurhash_validate(NULL, NULL, NULL);
+    if (status != BLURHASH_STATUS_NULL_POINTER) {
+        result = fail("blurhash_validate(NULL)", status);
+        goto out;
+    }
+
+out:
+    free(pixels);
+    return result;
+}
@@ -0,0 +1,54 @@
+//! Run under Node with:
+//!
+//! ```sh
+//! CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER=wasm-bindgen-test-runner \
+//!     cargo test --target wasm32-unknown-unknown --features wasm --test wasm
+//! ```
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use blurhash::wasm::{average_color, decode, encode, is_blurhash_valid};
+use wasm_bindgen::Clamped;
+use wasm_bindgen_test::*;
+
+const OCTOCAT: &str = "LNAdAqj[00aymkj[TKay9}ay-Sj[";
+
+#[wasm_bindgen_test]
+fn decode_to_image_data() {
+    let pixels = decode(OCTOCAT, 32, 24, 1.).unwrap();
+    assert_eq!(pixels.len(), 32 * 24 * 4);
+    assert_eq!(pixels.0, blurhash::decode(OCTOCAT, 32, 24, 1.).unwrap());
+}
+
+#[wasm_bindgen_test]
+fn encode_roundtrip() {
+    let pixels = decode(OCTOCAT, 32, 24, 1.).unwrap();
+    let hash = encode(pixels, 32, 24, 4, 3).unwrap();
+    assert_eq!(
+        hash,
+        blurhash::encode(4, 3, 32, 24, &decode(OCTOCAT, 32, 24, 1.).unwrap()).unwrap()
+    );
+
+    // The placeholder keeps roughly the average color of the original.
+    let original = average_color(OCTOCAT).unwrap();
+    for (c, original) in average_color(&hash).unwrap().iter().zip(original) {
+        assert!((*c as i32 - original as i32).abs() <= 8);
+    }
+}
+
+#[wasm_bindgen_test]
+fn validation() {
+    let valid = is_blurhash_valid(OCTOCAT);
+    assert!(valid.result);
+    assert_eq!(valid.error_reason, None);
+
+    let invalid = is_blurhash_valid("LNAdAq");
+    assert!(!invalid.result);
+    assert!(invalid.error_reason.is_some());
+}
+
+#[wasm_bindgen_test]
+fn errors() {
+    assert!(encode(Clamped(vec![0; 15]), 2, 2, 4, 3).is_err());
+    assert!(decode("LNAdA", 32, 32, 1.).is_err());
+    assert!(average_color("LNAdAqj[00aymkj[TKay9}ay-Sj\"").is_err());
+}